@@ -0,0 +1,200 @@
+// Files attached to a message. History entries only keep the metadata; images and large text
+// live once in a content-addressed blob store next to the memory file, keyed by SHA-256, so the
+// same screenshot sent twice or kept across regenerations is stored a single time.
+use base64::{Engine, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+const BLOB_DIR: &str = "attachments";
+// Text up to this size stays inline in the history entry.
+const INLINE_LIMIT: usize = 16 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum AttachmentKind {
+    Image,
+    Text,
+    Document,
+    Transcript,
+    Folder,
+}
+
+impl AttachmentKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Image => "image",
+            Self::Text => "text",
+            Self::Document => "document text",
+            Self::Transcript => "transcript",
+            Self::Folder => "folder",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub kind: AttachmentKind,
+    pub filename: String,
+    pub mime: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+}
+
+impl Attachment {
+    pub fn new(kind: AttachmentKind, filename: String, mime: &str, bytes: Vec<u8>) -> Result<Self, String> {
+        let size = bytes.len() as u64;
+        let (content, blob) = match String::from_utf8(bytes) {
+            Ok(text) if kind != AttachmentKind::Image && text.len() <= INLINE_LIMIT => (Some(text), None),
+            Ok(text) => (None, Some(store_blob(text.into_bytes())?)),
+            Err(e) => (None, Some(store_blob(e.into_bytes())?)),
+        };
+        Ok(Self { kind, filename, mime: mime.to_owned(), size, content, blob })
+    }
+
+    pub fn text(kind: AttachmentKind, filename: String, text: String) -> Result<Self, String> {
+        Self::new(kind, filename, "text/plain", text.into_bytes())
+    }
+
+    // Images from history files written before attachments had their own store.
+    pub fn from_data_url(url: &str, filename: String) -> Result<Self, String> {
+        let (header, data) = url
+            .strip_prefix("data:")
+            .and_then(|rest| rest.split_once(";base64,"))
+            .ok_or("not a base64 data URL")?;
+        let bytes = general_purpose::STANDARD.decode(data).map_err(|e| e.to_string())?;
+        Self::new(AttachmentKind::Image, filename, header, bytes)
+    }
+
+    pub fn bytes(&self) -> Result<Arc<Vec<u8>>, String> {
+        match (&self.content, &self.blob) {
+            (Some(text), _) => Ok(Arc::new(text.clone().into_bytes())),
+            (None, Some(key)) => load_blob(key),
+            (None, None) => Err("attachment has no content".to_owned()),
+        }
+    }
+
+    pub fn text_content(&self) -> Result<String, String> {
+        match self.content {
+            Some(ref text) => Ok(text.clone()),
+            None => String::from_utf8(self.bytes()?.to_vec()).map_err(|_| "attachment is not text".to_owned()),
+        }
+    }
+
+    pub fn data_url(&self) -> Result<String, String> {
+        Ok(format!("data:{};base64,{}", self.mime, general_purpose::STANDARD.encode(self.bytes()?.as_slice())))
+    }
+
+    pub fn summary(&self) -> String {
+        format!("{} ({}, {})", self.filename, self.kind.label(), format_size(self.size))
+    }
+
+    // Writes the attachment to the temp folder and hands it to the system's default application.
+    pub fn open(&self) -> Result<(), String> {
+        let bytes = self.bytes()?;
+        let path = self.temp_path(&bytes);
+        fs::create_dir_all(path.parent().unwrap_or(&path)).map_err(|e| e.to_string())?;
+        fs::write(&path, bytes.as_slice()).map_err(|e| e.to_string())?;
+        open_with_system(&path)
+    }
+
+    // Each content gets its own folder named after its hash, so two attachments with the same
+    // filename never overwrite a copy a viewer still has open.
+    fn temp_path(&self, bytes: &[u8]) -> PathBuf {
+        let hash = format!("{:x}", Sha256::digest(bytes));
+        let name = Path::new(&self.filename).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let name = match self.kind {
+            AttachmentKind::Image | AttachmentKind::Text => name,
+            AttachmentKind::Folder => format!("{}.md", name),
+            AttachmentKind::Document | AttachmentKind::Transcript => format!("{}.txt", name),
+        };
+        std::env::temp_dir().join("ai-chat-attachments").join(&hash[..16]).join(name)
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{} KB", b / 1024),
+        b => format!("{} B", b),
+    }
+}
+
+fn blob_dir() -> PathBuf {
+    // Tests keep their blobs out of the working directory.
+    if cfg!(test) {
+        std::env::temp_dir().join("ai-chat-test-attachments")
+    } else {
+        PathBuf::from(BLOB_DIR)
+    }
+}
+
+fn blob_path(key: &str) -> PathBuf {
+    blob_dir().join(key)
+}
+
+fn store_blob(bytes: Vec<u8>) -> Result<String, String> {
+    let key = format!("{:x}", Sha256::digest(&bytes));
+    let path = blob_path(&key);
+    if !path.exists() {
+        fs::create_dir_all(blob_dir()).map_err(|e| e.to_string())?;
+        fs::write(&path, &bytes).map_err(|e| format!("could not store attachment: {}", e))?;
+    }
+    Ok(key)
+}
+
+// Read on every use rather than cached, so memory does not grow with the history.
+fn load_blob(key: &str) -> Result<Arc<Vec<u8>>, String> {
+    let bytes = fs::read(blob_path(key)).map_err(|e| format!("attachment is no longer available: {}", e))?;
+    Ok(Arc::new(bytes))
+}
+
+// Deletes stored blobs that no history entry points at any more.
+pub fn prune_blobs(referenced: &HashSet<String>) {
+    let Ok(entries) = fs::read_dir(blob_dir()) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let key = entry.file_name().to_string_lossy().to_string();
+        if !referenced.contains(&key) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+fn open_with_system(path: &Path) -> Result<(), String> {
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(not(any(windows, target_os = "macos")))]
+    let mut command = std::process::Command::new("xdg-open");
+    command.arg(path).spawn().map(|_| ()).map_err(|e| format!("could not open {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_name_different_content_opens_separate_files() {
+        let first = Attachment::text(AttachmentKind::Text, "notes/README.md".into(), "first".into()).unwrap();
+        let second = Attachment::text(AttachmentKind::Text, "other/README.md".into(), "second".into()).unwrap();
+        let first_path = first.temp_path(&first.bytes().unwrap());
+        let second_path = second.temp_path(&second.bytes().unwrap());
+        assert_ne!(first_path, second_path);
+        assert_eq!(first_path.file_name(), second_path.file_name());
+        assert_eq!(first_path, first.temp_path(&first.bytes().unwrap()));
+    }
+}
@@ -0,0 +1,158 @@
+// Plain-text extraction for document uploads, so the model gets readable text instead of raw bytes.
+// PDFs go through lopdf page by page; DOCX is a zip whose word/document.xml is walked for
+// paragraphs, headings and tables.
+use quick_xml::{Reader, events::Event};
+use std::{io::Read, path::Path};
+
+// Long documents are cut here; the note at the end tells the model what was left out.
+const MAX_PDF_PAGES: usize = 100;
+const MAX_DOCUMENT_CHARS: usize = 100_000;
+
+pub fn extract_document_text(path: &Path) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let text = match extension.as_str() {
+        "pdf" => extract_pdf_text(&bytes)?,
+        "docx" => extract_docx_text(&bytes)?,
+        other => return Err(format!("no text extractor for .{} files", other)),
+    };
+    Ok(cap_chars(text))
+}
+
+fn cap_chars(text: String) -> String {
+    match text.char_indices().nth(MAX_DOCUMENT_CHARS) {
+        Some((cut, _)) => format!(
+            "{}\n[Truncated: first {} of {} characters]",
+            &text[..cut],
+            MAX_DOCUMENT_CHARS,
+            text.chars().count()
+        ),
+        None => text,
+    }
+}
+
+fn extract_pdf_text(bytes: &[u8]) -> Result<String, String> {
+    let document = lopdf::Document::load_mem(bytes).map_err(|e| format!("could not read PDF: {}", e))?;
+    let encrypted = document.is_encrypted();
+    let pages: Vec<u32> = document.get_pages().keys().copied().collect();
+    let mut text = String::new();
+    for &page in pages.iter().take(MAX_PDF_PAGES) {
+        match document.extract_text(&[page]) {
+            Ok(page_text) => {
+                text.push_str(page_text.trim_end());
+                text.push_str("\n\n");
+            }
+            Err(_) if encrypted => return Err("the PDF is encrypted; remove the password and try again".to_owned()),
+            Err(e) => eprintln!("[UPLOAD] Skipping PDF page {}: {}", page, e),
+        }
+    }
+    if text.trim().is_empty() {
+        return Err(if encrypted {
+            "the PDF is encrypted; remove the password and try again".to_owned()
+        } else {
+            "the PDF has no text layer (scanned or image-only pages); run it through OCR first".to_owned()
+        });
+    }
+    if pages.len() > MAX_PDF_PAGES {
+        text.push_str(&format!("[Truncated: first {} of {} pages]", MAX_PDF_PAGES, pages.len()));
+    }
+    Ok(text.trim_end().to_owned())
+}
+
+fn extract_docx_text(bytes: &[u8]) -> Result<String, String> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).map_err(|e| format!("not a DOCX file: {}", e))?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .map_err(|e| format!("not a DOCX file: {}", e))?
+        .read_to_string(&mut xml)
+        .map_err(|e| e.to_string())?;
+    docx_xml_to_text(&xml)
+}
+
+// Headings become markdown "#" lines and table rows "| a | b |", which models read well.
+fn docx_xml_to_text(xml: &str) -> Result<String, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut lines: Vec<String> = Vec::new();
+    let mut paragraph = String::new();
+    let mut heading: Option<usize> = None;
+    let mut in_text = false;
+    let mut row: Option<Vec<String>> = None;
+    let mut cell: Option<Vec<String>> = None;
+    loop {
+        let event = reader.read_event().map_err(|e| format!("malformed DOCX: {}", e))?;
+        match event {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"pStyle" => {
+                let style = e
+                    .attributes()
+                    .filter_map(Result::ok)
+                    .find(|attr| attr.key.local_name().as_ref() == b"val")
+                    .map(|attr| String::from_utf8_lossy(&attr.value).to_string())
+                    .unwrap_or_default();
+                heading = match style.as_str() {
+                    "Title" => Some(1),
+                    _ => style.strip_prefix("Heading").and_then(|level| level.parse().ok()),
+                };
+            }
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"p" => {
+                    paragraph.clear();
+                    heading = None;
+                }
+                b"t" => in_text = true,
+                b"tr" => row = Some(Vec::new()),
+                b"tc" => cell = Some(Vec::new()),
+                _ => {}
+            },
+            Event::Empty(e) => match e.local_name().as_ref() {
+                b"tab" => paragraph.push('\t'),
+                b"br" | b"cr" => paragraph.push('\n'),
+                _ => {}
+            },
+            Event::Text(text) if in_text => {
+                paragraph.push_str(&text.unescape().map_err(|e| format!("malformed DOCX: {}", e))?);
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => {
+                    let line = match heading {
+                        Some(level) if !paragraph.trim().is_empty() => {
+                            format!("{} {}", "#".repeat(level.clamp(1, 6)), paragraph.trim())
+                        }
+                        _ => paragraph.trim_end().to_owned(),
+                    };
+                    match cell.as_mut() {
+                        Some(cell) => cell.push(line),
+                        None => lines.push(line),
+                    }
+                }
+                b"tc" => {
+                    if let (Some(row), Some(cell)) = (row.as_mut(), cell.take()) {
+                        row.push(cell.join(" ").replace('|', "\\|"));
+                    }
+                }
+                b"tr" => {
+                    if let Some(row) = row.take() {
+                        lines.push(format!("| {} |", row.join(" | ")));
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    // Runs of empty paragraphs collapse to one blank line.
+    let mut text = String::new();
+    for line in lines {
+        if line.is_empty() && (text.is_empty() || text.ends_with("\n\n")) {
+            continue;
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+    if text.trim().is_empty() {
+        return Err("the document contains no text".to_owned());
+    }
+    Ok(text.trim_end().to_owned())
+}
@@ -0,0 +1,255 @@
+// "Attach folder": walks a directory honouring .gitignore files and the user's include/exclude
+// globs, estimates tokens per file from its size and picks files until the budget is used up.
+// Only the chosen files are read, and they are sent as labelled fenced blocks, one per file.
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+use crate::messages::{fenced_file, language_for};
+
+// Bigger files are listed but never read; they would blow any sensible budget anyway.
+const MAX_TEXT_FILE_BYTES: u64 = 1024 * 1024;
+// Stops runaway scans of home directories and the like.
+const MAX_SCANNED_FILES: usize = 5000;
+// Bytes inspected when deciding whether a file is binary.
+const BINARY_SNIFF_BYTES: usize = 8000;
+
+const MATCH: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FolderOptions {
+    // Comma-separated globs; an empty include list takes every file. A bare folder name in the
+    // exclude list skips that folder wherever it appears.
+    pub include: String,
+    pub exclude: String,
+    pub token_budget: usize,
+}
+
+impl Default for FolderOptions {
+    fn default() -> Self {
+        Self {
+            include: String::new(),
+            exclude: "target, node_modules, *.lock".to_owned(),
+            token_budget: 32_000,
+        }
+    }
+}
+
+pub struct FolderFile {
+    // Relative to the attached folder, always with '/' separators.
+    pub relative: String,
+    pub tokens: usize,
+    // Why the file cannot be attached (binary, too large, unreadable).
+    pub skipped: Option<String>,
+    pub selected: bool,
+}
+
+pub struct FolderScan {
+    pub root: PathBuf,
+    pub files: Vec<FolderFile>,
+    pub truncated: bool,
+}
+
+impl FolderScan {
+    pub fn selected_tokens(&self) -> usize {
+        self.files.iter().filter(|f| f.selected).map(|f| f.tokens).sum()
+    }
+
+    pub fn selected_count(&self) -> usize {
+        self.files.iter().filter(|f| f.selected).count()
+    }
+
+    // Files are taken in listing order; one that does not fit is left out, smaller ones after it may still fit.
+    pub fn select_within(&mut self, budget: usize) {
+        let mut used = 0;
+        for file in self.files.iter_mut().filter(|f| f.skipped.is_none()) {
+            file.selected = used + file.tokens <= budget;
+            if file.selected {
+                used += file.tokens;
+            }
+        }
+    }
+
+    // One labelled fenced block per selected file, in the order they were listed.
+    pub fn assemble(&self) -> Result<String, String> {
+        let mut blocks = Vec::new();
+        for file in self.files.iter().filter(|f| f.selected) {
+            let text = read_text_file(&self.root.join(&file.relative))
+                .map_err(|e| format!("{}: {}", file.relative, e))?;
+            blocks.push(fenced_file(&file.relative, language_for(&file.relative), &text));
+        }
+        Ok(blocks.join("\n\n"))
+    }
+}
+
+// Roughly four bytes per token for code and English prose.
+pub fn estimate_tokens(bytes: u64) -> usize {
+    bytes.div_ceil(4) as usize
+}
+
+pub fn read_text_file(path: &Path) -> Result<String, String> {
+    let size = path.metadata().map_err(|e| e.to_string())?.len();
+    if size > MAX_TEXT_FILE_BYTES {
+        return Err(format!("too large ({} KB)", size / 1024));
+    }
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    if looks_binary(&bytes) {
+        return Err("binary file".to_owned());
+    }
+    String::from_utf8(bytes).map_err(|_| "binary file".to_owned())
+}
+
+fn looks_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(BINARY_SNIFF_BYTES).any(|&b| b == 0)
+}
+
+// Size and a peek at the first bytes are enough to list a file; its contents are read on attach.
+fn check_file(path: &Path) -> Result<u64, String> {
+    let size = path.metadata().map_err(|e| e.to_string())?.len();
+    if size > MAX_TEXT_FILE_BYTES {
+        return Err(format!("too large ({} KB)", size / 1024));
+    }
+    let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+    std::fs::File::open(path)
+        .and_then(|file| file.take(BINARY_SNIFF_BYTES as u64).read_to_end(&mut head))
+        .map_err(|e| e.to_string())?;
+    if looks_binary(&head) {
+        return Err("binary file".to_owned());
+    }
+    Ok(size)
+}
+
+pub fn scan_folder(root: &Path, options: &FolderOptions) -> Result<FolderScan, String> {
+    if !root.is_dir() {
+        return Err(format!("{} is not a folder", root.display()));
+    }
+    let include = parse_globs(&options.include)?;
+    let exclude = parse_globs(&options.exclude)?;
+    let mut ignores: HashMap<PathBuf, Vec<IgnoreRule>> = HashMap::new();
+    let mut files = Vec::new();
+    let mut truncated = false;
+    let walker = WalkDir::new(root).sort_by_file_name().into_iter().filter_entry(|entry| {
+        if entry.depth() == 0 {
+            return true;
+        }
+        let relative = relative_path(root, entry.path());
+        let is_dir = entry.file_type().is_dir();
+        if is_dir && entry.file_name() == ".git" {
+            return false;
+        }
+        !is_ignored(root, entry.path(), is_dir, &mut ignores) && !exclude.iter().any(|glob| glob_matches(glob, &relative))
+    });
+    for entry in walker {
+        let entry = entry.map_err(|e| e.to_string())?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = relative_path(root, entry.path());
+        if !include.is_empty() && !include.iter().any(|glob| glob_matches(glob, &relative)) {
+            continue;
+        }
+        if files.len() == MAX_SCANNED_FILES {
+            truncated = true;
+            break;
+        }
+        let (tokens, skipped) = match check_file(entry.path()) {
+            Ok(size) => (estimate_tokens(size), None),
+            Err(e) => (0, Some(e)),
+        };
+        files.push(FolderFile { relative, tokens, skipped, selected: false });
+    }
+    let mut scan = FolderScan { root: root.to_path_buf(), files, truncated };
+    scan.select_within(options.token_budget);
+    Ok(scan)
+}
+
+fn parse_globs(list: &str) -> Result<Vec<Pattern>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|glob| !glob.is_empty())
+        .map(|glob| Pattern::new(glob).map_err(|e| format!("bad pattern \"{}\": {}", glob, e)))
+        .collect()
+}
+
+// Globs without a '/' apply to the file name at any depth, like .gitignore.
+fn glob_matches(glob: &Pattern, relative: &str) -> bool {
+    if glob.as_str().contains('/') {
+        glob.matches_with(relative, MATCH)
+    } else {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        glob.matches_with(name, MATCH)
+    }
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+struct IgnoreRule {
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+// Parses the common subset of .gitignore: comments, "!" negation, trailing "/" for directories
+// and patterns anchored to their folder when they contain a '/'.
+fn load_ignore_rules(dir: &Path) -> Vec<IgnoreRule> {
+    let Ok(text) = std::fs::read_to_string(dir.join(".gitignore")) else {
+        return Vec::new();
+    };
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let pattern = Pattern::new(line.trim_start_matches('/')).ok()?;
+            Some(IgnoreRule { pattern, negated, dir_only, anchored })
+        })
+        .collect()
+}
+
+// Checks every .gitignore from the root down to the entry's folder; the last matching rule wins.
+fn is_ignored(root: &Path, path: &Path, is_dir: bool, cache: &mut HashMap<PathBuf, Vec<IgnoreRule>>) -> bool {
+    let mut ignored = false;
+    let Some(parent) = path.parent() else {
+        return false;
+    };
+    let mut dirs: Vec<&Path> = parent.ancestors().take_while(|dir| dir.starts_with(root)).collect();
+    dirs.reverse();
+    for dir in dirs {
+        let rules = cache.entry(dir.to_path_buf()).or_insert_with(|| load_ignore_rules(dir));
+        let relative = relative_path(dir, path);
+        let name = relative.rsplit('/').next().unwrap_or(&relative);
+        for rule in rules.iter() {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let target = if rule.anchored { relative.as_str() } else { name };
+            if rule.pattern.matches_with(target, MATCH) {
+                ignored = !rule.negated;
+            }
+        }
+    }
+    ignored
+}
@@ -0,0 +1,622 @@
+// Global hotkeys behind a small backend trait: win-hotkeys on Windows, X11 key grabs on Linux,
+// and a backend that registers nothing where neither is available (Wayland, headless).
+// Backends only report presses and releases; which command a key sends is decided here.
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+// Named keys accepted after the modifiers, besides single letters/digits and F1-F24.
+const NAMED_KEYS: &[&str] = &[
+    "SPACE", "ENTER", "ESCAPE", "TAB", "BACKSPACE", "INSERT", "DELETE", "HOME", "END", "PAGEUP", "PAGEDOWN", "LEFT",
+    "UP", "RIGHT", "DOWN", "PAUSE", "PRINT",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HotkeyCommand {
+    ToggleSTT,
+    PushToTalkStart,
+    PushToTalkStop,
+    StopSpeech,
+    ToggleTTS,
+    SpeakClipboard,
+    AskClipboard,
+    Regenerate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyEvent {
+    Pressed,
+    Released,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HotkeyAction {
+    Press(HotkeyCommand),
+    // Sends one command when the key goes down and another when it comes back up.
+    Hold { start: HotkeyCommand, stop: HotkeyCommand },
+}
+
+impl HotkeyAction {
+    pub fn command_for(self, event: KeyEvent) -> Option<HotkeyCommand> {
+        match (self, event) {
+            (HotkeyAction::Press(command), KeyEvent::Pressed) => Some(command),
+            (HotkeyAction::Press(_), KeyEvent::Released) => None,
+            (HotkeyAction::Hold { start, .. }, KeyEvent::Pressed) => Some(start),
+            (HotkeyAction::Hold { stop, .. }, KeyEvent::Released) => Some(stop),
+        }
+    }
+}
+
+pub struct HotkeyBinding {
+    // Shown in error messages, e.g. "STT" or "Stop speech".
+    pub name: &'static str,
+    pub hotkey: String,
+    pub action: HotkeyAction,
+}
+
+// Bindings for the one-shot commands, remembered in settings. Empty means unbound.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandHotkeys {
+    pub stop_speech: String,
+    pub toggle_tts: String,
+    pub speak_clipboard: String,
+    pub ask_clipboard: String,
+    pub regenerate: String,
+}
+
+impl CommandHotkeys {
+    // Label, binding and command for each entry, in the order the settings window lists them.
+    pub fn entries_mut(&mut self) -> [(&'static str, &mut String, HotkeyCommand); 5] {
+        [
+            ("Stop speech", &mut self.stop_speech, HotkeyCommand::StopSpeech),
+            ("Toggle TTS", &mut self.toggle_tts, HotkeyCommand::ToggleTTS),
+            ("Speak clipboard", &mut self.speak_clipboard, HotkeyCommand::SpeakClipboard),
+            ("Ask about clipboard", &mut self.ask_clipboard, HotkeyCommand::AskClipboard),
+            ("Regenerate last reply", &mut self.regenerate, HotkeyCommand::Regenerate),
+        ]
+    }
+
+    pub fn bindings(&self) -> Vec<HotkeyBinding> {
+        let mut hotkeys = self.clone();
+        hotkeys
+            .entries_mut()
+            .into_iter()
+            .filter(|(_, hotkey, _)| !hotkey.trim().is_empty())
+            .map(|(name, hotkey, command)| HotkeyBinding {
+                name,
+                hotkey: hotkey.clone(),
+                action: HotkeyAction::Press(command),
+            })
+            .collect()
+    }
+}
+
+// A parsed binding. The key is stored upper-case, e.g. "B", "F5" or "SPACE".
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hotkey {
+    pub key: String,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub super_key: bool,
+}
+
+impl Hotkey {
+    // Parses combinations like "Ctrl+Shift+B": any modifiers followed by one key.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut keys: Vec<&str> = text.split('+').map(str::trim).filter(|key| !key.is_empty()).collect();
+        let key = keys.pop().ok_or_else(|| "empty hotkey".to_owned())?;
+        let mut hotkey = Hotkey {
+            key: canonical_key(key).ok_or_else(|| format!("unknown key \"{}\" in \"{}\"", key, text))?,
+            ctrl: false,
+            shift: false,
+            alt: false,
+            super_key: false,
+        };
+        for modifier in keys {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => hotkey.ctrl = true,
+                "shift" => hotkey.shift = true,
+                "alt" => hotkey.alt = true,
+                "win" | "super" | "meta" => hotkey.super_key = true,
+                other => return Err(format!("unknown modifier \"{}\" in \"{}\"", other, text)),
+            }
+        }
+        Ok(hotkey)
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (held, name) in [(self.ctrl, "Ctrl+"), (self.shift, "Shift+"), (self.alt, "Alt+"), (self.super_key, "Super+")] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+        let mut chars = self.key.chars();
+        match chars.next() {
+            Some(first) => write!(f, "{}{}", first, chars.as_str().to_ascii_lowercase()),
+            None => Ok(()),
+        }
+    }
+}
+
+fn canonical_key(key: &str) -> Option<String> {
+    let key = key.to_ascii_uppercase();
+    let key = match key.as_str() {
+        "RETURN" => "ENTER".to_owned(),
+        "ESC" => "ESCAPE".to_owned(),
+        "DEL" => "DELETE".to_owned(),
+        "PGUP" => "PAGEUP".to_owned(),
+        "PGDN" => "PAGEDOWN".to_owned(),
+        _ => key,
+    };
+    let single = key.len() == 1 && key.chars().all(|c| c.is_ascii_alphanumeric());
+    let function = key
+        .strip_prefix('F')
+        .and_then(|n| n.parse::<u8>().ok())
+        .is_some_and(|n| (1..=24).contains(&n));
+    (single || function || NAMED_KEYS.contains(&key.as_str())).then_some(key)
+}
+
+pub type KeyCallback = Arc<dyn Fn(KeyEvent) + Send + Sync>;
+
+pub trait HotkeyBackend {
+    // Backends call the callback once per press and once per release, without key repeat.
+    fn register(&mut self, hotkey: &Hotkey, callback: KeyCallback) -> Result<(), String>;
+    // Starts listening on a background thread until the handle is stopped.
+    fn spawn(self: Box<Self>) -> HotkeyHandle;
+}
+
+pub struct HotkeyHandle {
+    stop: Box<dyn FnOnce() + Send>,
+}
+
+impl HotkeyHandle {
+    pub fn new(stop: impl FnOnce() + Send + 'static) -> Self {
+        Self { stop: Box::new(stop) }
+    }
+
+    pub fn stop(self) {
+        (self.stop)()
+    }
+}
+
+// Registers every binding on the backend and starts it. Problems come back as messages for the chat.
+pub fn start_hotkeys(
+    mut backend: Box<dyn HotkeyBackend>,
+    bindings: &[HotkeyBinding],
+    on_command: impl Fn(HotkeyCommand) + Send + Sync + 'static,
+) -> (HotkeyHandle, Vec<String>) {
+    let on_command = Arc::new(on_command);
+    let mut errors = Vec::new();
+    for binding in bindings {
+        let hotkey = match Hotkey::parse(&binding.hotkey) {
+            Ok(hotkey) => hotkey,
+            Err(e) => {
+                errors.push(format!("Invalid {} hotkey: {}", binding.name, e));
+                continue;
+            }
+        };
+        let on_command = on_command.clone();
+        let action = binding.action;
+        let callback: KeyCallback = Arc::new(move |event| {
+            if let Some(command) = action.command_for(event) {
+                on_command(command);
+            }
+        });
+        if let Err(e) = backend.register(&hotkey, callback) {
+            errors.push(format!("Could not register hotkey {}: {}", hotkey, e));
+        }
+    }
+    (backend.spawn(), errors)
+}
+
+// The backend for this platform and session, with a note when hotkeys cannot work here.
+pub fn platform_backend() -> (Box<dyn HotkeyBackend>, Option<String>) {
+    #[cfg(windows)]
+    {
+        (Box::new(windows::WindowsBackend::new()), None)
+    }
+    #[cfg(target_os = "linux")]
+    {
+        match x11::X11Backend::connect() {
+            Ok(backend) => (Box::new(backend), None),
+            Err(e) => (
+                Box::new(NoopBackend),
+                Some(format!("Global hotkeys are unavailable without an X11 display ({}).", e)),
+            ),
+        }
+    }
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        (Box::new(NoopBackend), Some("Global hotkeys are not supported on this platform.".to_owned()))
+    }
+}
+
+// Accepts every binding and never fires.
+pub struct NoopBackend;
+
+impl HotkeyBackend for NoopBackend {
+    fn register(&mut self, _hotkey: &Hotkey, _callback: KeyCallback) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn spawn(self: Box<Self>) -> HotkeyHandle {
+        HotkeyHandle::new(|| ())
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::*;
+    use std::{thread, time::Duration};
+    use win_hotkeys::{HotkeyManager, VKey, state::KeyboardState};
+
+    pub struct WindowsBackend {
+        manager: HotkeyManager<()>,
+    }
+
+    impl WindowsBackend {
+        pub fn new() -> Self {
+            Self { manager: HotkeyManager::new() }
+        }
+    }
+
+    fn vkeys(hotkey: &Hotkey) -> Result<(VKey, Vec<VKey>), String> {
+        let trigger = match hotkey.key.as_str() {
+            "ENTER" => Ok(VKey::Return),
+            "BACKSPACE" => Ok(VKey::Back),
+            "PAGEUP" => Ok(VKey::Prior),
+            "PAGEDOWN" => Ok(VKey::Next),
+            "PRINT" => Ok(VKey::Snapshot),
+            key => VKey::from_keyname(key).map_err(|e| e.to_string()),
+        }?;
+        let modifiers = [
+            (hotkey.ctrl, VKey::Control),
+            (hotkey.shift, VKey::Shift),
+            (hotkey.alt, VKey::Menu),
+            (hotkey.super_key, VKey::LWin),
+        ];
+        Ok((trigger, modifiers.into_iter().filter(|(held, _)| *held).map(|(_, key)| key).collect()))
+    }
+
+    impl HotkeyBackend for WindowsBackend {
+        fn register(&mut self, hotkey: &Hotkey, callback: KeyCallback) -> Result<(), String> {
+            let (trigger, modifiers) = vkeys(hotkey)?;
+            let held = Arc::new(AtomicBool::new(false));
+            // The manager only reports key presses (repeatedly while held), so the first
+            // press is passed on and a watcher thread waits for the release.
+            self.manager
+                .register_hotkey(trigger, &modifiers, move || {
+                    if held.swap(true, Ordering::Relaxed) {
+                        return;
+                    }
+                    callback(KeyEvent::Pressed);
+                    let held = held.clone();
+                    let callback = callback.clone();
+                    thread::spawn(move || {
+                        while KeyboardState::get_async_key_state(trigger.to_vk_code()) {
+                            thread::sleep(Duration::from_millis(20));
+                        }
+                        held.store(false, Ordering::Relaxed);
+                        callback(KeyEvent::Released);
+                    });
+                })
+                .map(|_| ())
+                .map_err(|e| format!("{:?}", e))
+        }
+
+        fn spawn(self: Box<Self>) -> HotkeyHandle {
+            let mut manager = self.manager;
+            let interrupt_handle = manager.interrupt_handle();
+            thread::spawn(move || {
+                manager.event_loop();
+            });
+            HotkeyHandle::new(move || interrupt_handle.interrupt())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use super::*;
+    use std::{thread, time::Duration};
+    use x11rb::{
+        connection::Connection,
+        protocol::{
+            Event,
+            xkb::{self, ConnectionExt as _},
+            xproto::{ConnectionExt as _, GrabMode, ModMask, Window},
+        },
+        rust_connection::RustConnection,
+    };
+
+    // Lock keys that must not stop a binding from matching: Caps Lock (Lock) and Num Lock (Mod2).
+    const IGNORED_MODIFIERS: [u16; 4] = [0, 0x02, 0x10, 0x12];
+
+    pub struct X11Backend {
+        connection: RustConnection,
+        root: Window,
+        bindings: Vec<(u8, u16, KeyCallback)>,
+        // Which bindings are down, so a release matches even if the modifiers came up first.
+        pressed: Vec<bool>,
+    }
+
+    impl X11Backend {
+        pub fn connect() -> Result<Self, String> {
+            let (connection, screen) = x11rb::connect(None).map_err(|e| e.to_string())?;
+            let root = connection.setup().roots[screen].root;
+            // Without this, holding a key sends release/press pairs that would end push-to-talk.
+            let detectable = xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT;
+            let repeat = connection
+                .xkb_use_extension(1, 0)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .and_then(|_| {
+                    connection
+                        .xkb_per_client_flags(xkb::ID::USE_CORE_KBD.into(), detectable, detectable, 0u32.into(), 0u32.into(), 0u32.into())
+                        .ok()
+                })
+                .and_then(|cookie| cookie.reply().ok());
+            if repeat.is_none() {
+                eprintln!("[HOTKEY] XKB detectable auto-repeat is unavailable; held keys may repeat");
+            }
+            Ok(Self { connection, root, bindings: Vec::new(), pressed: Vec::new() })
+        }
+
+        fn keycode(&self, key: &str) -> Result<u8, String> {
+            let keysym = keysym(key).ok_or_else(|| format!("no X11 keysym for {}", key))?;
+            let setup = self.connection.setup();
+            let count = setup.max_keycode - setup.min_keycode + 1;
+            let mapping = self
+                .connection
+                .get_keyboard_mapping(setup.min_keycode, count)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?;
+            let per_keycode = mapping.keysyms_per_keycode.max(1) as usize;
+            mapping
+                .keysyms
+                .chunks(per_keycode)
+                .position(|syms| syms.contains(&keysym))
+                .map(|index| setup.min_keycode + index as u8)
+                .ok_or_else(|| format!("{} is not on this keyboard layout", key))
+        }
+    }
+
+    // Letters are matched by their lower-case keysym, which is what the key produces unshifted.
+    fn keysym(key: &str) -> Option<u32> {
+        if key.len() == 1 {
+            let c = key.chars().next()?;
+            return Some(c.to_ascii_lowercase() as u32);
+        }
+        if let Some(n) = key.strip_prefix('F').and_then(|n| n.parse::<u32>().ok()) {
+            return Some(0xffbe + n - 1);
+        }
+        Some(match key {
+            "SPACE" => 0x0020,
+            "ENTER" => 0xff0d,
+            "ESCAPE" => 0xff1b,
+            "TAB" => 0xff09,
+            "BACKSPACE" => 0xff08,
+            "INSERT" => 0xff63,
+            "DELETE" => 0xffff,
+            "HOME" => 0xff50,
+            "END" => 0xff57,
+            "PAGEUP" => 0xff55,
+            "PAGEDOWN" => 0xff56,
+            "LEFT" => 0xff51,
+            "UP" => 0xff52,
+            "RIGHT" => 0xff53,
+            "DOWN" => 0xff54,
+            "PAUSE" => 0xff13,
+            "PRINT" => 0xff61,
+            _ => return None,
+        })
+    }
+
+    fn modifier_mask(hotkey: &Hotkey) -> u16 {
+        let mut mask = 0;
+        for (held, modifier) in [
+            (hotkey.ctrl, ModMask::CONTROL),
+            (hotkey.shift, ModMask::SHIFT),
+            (hotkey.alt, ModMask::M1),
+            (hotkey.super_key, ModMask::M4),
+        ] {
+            if held {
+                mask |= u16::from(modifier);
+            }
+        }
+        mask
+    }
+
+    impl HotkeyBackend for X11Backend {
+        fn register(&mut self, hotkey: &Hotkey, callback: KeyCallback) -> Result<(), String> {
+            let keycode = self.keycode(&hotkey.key)?;
+            let modifiers = modifier_mask(hotkey);
+            for ignored in IGNORED_MODIFIERS {
+                self.connection
+                    .grab_key(false, self.root, ModMask::from(modifiers | ignored), keycode, GrabMode::ASYNC, GrabMode::ASYNC)
+                    .map_err(|e| e.to_string())?
+                    .check()
+                    .map_err(|e| format!("already taken by another application ({:?})", e))?;
+            }
+            self.bindings.push((keycode, modifiers, callback));
+            self.pressed.push(false);
+            Ok(())
+        }
+
+        fn spawn(mut self: Box<Self>) -> HotkeyHandle {
+            let stop = Arc::new(AtomicBool::new(false));
+            let running = stop.clone();
+            let relevant = u16::from(ModMask::CONTROL | ModMask::SHIFT | ModMask::M1 | ModMask::M4);
+            thread::spawn(move || {
+                // Grabs end with the connection when this thread returns.
+                while !running.load(Ordering::Relaxed) {
+                    let (keycode, state, event) = match self.connection.poll_for_event() {
+                        Ok(Some(Event::KeyPress(e))) => (e.detail, u16::from(e.state), KeyEvent::Pressed),
+                        Ok(Some(Event::KeyRelease(e))) => (e.detail, u16::from(e.state), KeyEvent::Released),
+                        Ok(Some(_)) => continue,
+                        Ok(None) => {
+                            thread::sleep(Duration::from_millis(20));
+                            continue;
+                        }
+                        Err(e) => {
+                            eprintln!("[HOTKEY] X11 connection lost: {}", e);
+                            return;
+                        }
+                    };
+                    for ((code, modifiers, callback), pressed) in self.bindings.iter().zip(&mut self.pressed) {
+                        let matches = match event {
+                            KeyEvent::Pressed => *modifiers == state & relevant && !*pressed,
+                            KeyEvent::Released => *pressed,
+                        };
+                        if *code == keycode && matches {
+                            *pressed = event == KeyEvent::Pressed;
+                            callback(event);
+                        }
+                    }
+                }
+            });
+            HotkeyHandle::new(move || stop.store(true, Ordering::Relaxed))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Records what was registered and lets a test press and release those keys by hand.
+    #[derive(Clone, Default)]
+    struct FakeBackend {
+        registered: Arc<Mutex<Vec<(Hotkey, KeyCallback)>>>,
+        rejected: Option<String>,
+        stopped: Arc<AtomicBool>,
+    }
+
+    impl FakeBackend {
+        fn fire(&self, hotkey: &str, event: KeyEvent) {
+            let hotkey = Hotkey::parse(hotkey).unwrap();
+            let registered = self.registered.lock().unwrap();
+            let (_, callback) = registered.iter().find(|(key, _)| *key == hotkey).expect("hotkey was not registered");
+            callback(event);
+        }
+    }
+
+    impl HotkeyBackend for FakeBackend {
+        fn register(&mut self, hotkey: &Hotkey, callback: KeyCallback) -> Result<(), String> {
+            if self.rejected.as_deref() == Some(hotkey.key.as_str()) {
+                return Err("already taken".to_owned());
+            }
+            self.registered.lock().unwrap().push((hotkey.clone(), callback));
+            Ok(())
+        }
+
+        fn spawn(self: Box<Self>) -> HotkeyHandle {
+            let stopped = self.stopped.clone();
+            HotkeyHandle::new(move || stopped.store(true, Ordering::Relaxed))
+        }
+    }
+
+    fn binding(name: &'static str, hotkey: &str, action: HotkeyAction) -> HotkeyBinding {
+        HotkeyBinding { name, hotkey: hotkey.to_owned(), action }
+    }
+
+    fn start(backend: &FakeBackend, bindings: &[HotkeyBinding]) -> (HotkeyHandle, Vec<String>, Arc<Mutex<Vec<HotkeyCommand>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let (handle, errors) = start_hotkeys(Box::new(backend.clone()), bindings, move |command| sink.lock().unwrap().push(command));
+        (handle, errors, received)
+    }
+
+    #[test]
+    fn parses_modifiers_and_keys() {
+        let hotkey = Hotkey::parse("ctrl + Shift+b").unwrap();
+        assert_eq!(hotkey, Hotkey { key: "B".to_owned(), ctrl: true, shift: true, alt: false, super_key: false });
+        assert_eq!(hotkey.to_string(), "Ctrl+Shift+B");
+        assert_eq!(Hotkey::parse("Alt+Win+F12").unwrap().to_string(), "Alt+Super+F12");
+        assert_eq!(Hotkey::parse("Control+esc").unwrap().key, "ESCAPE");
+        assert_eq!(Hotkey::parse("Ctrl+Space").unwrap().to_string(), "Ctrl+Space");
+    }
+
+    #[test]
+    fn rejects_bad_hotkeys() {
+        assert!(Hotkey::parse("").is_err());
+        assert!(Hotkey::parse("Ctrl+").is_err());
+        assert!(Hotkey::parse("Hyper+B").is_err());
+        assert!(Hotkey::parse("Ctrl+F25").is_err());
+        assert!(Hotkey::parse("Ctrl+Banana").is_err());
+    }
+
+    #[test]
+    fn press_actions_ignore_release() {
+        let action = HotkeyAction::Press(HotkeyCommand::ToggleSTT);
+        assert_eq!(action.command_for(KeyEvent::Pressed), Some(HotkeyCommand::ToggleSTT));
+        assert_eq!(action.command_for(KeyEvent::Released), None);
+    }
+
+    #[test]
+    fn hold_actions_start_and_stop() {
+        let action = HotkeyAction::Hold { start: HotkeyCommand::PushToTalkStart, stop: HotkeyCommand::PushToTalkStop };
+        assert_eq!(action.command_for(KeyEvent::Pressed), Some(HotkeyCommand::PushToTalkStart));
+        assert_eq!(action.command_for(KeyEvent::Released), Some(HotkeyCommand::PushToTalkStop));
+    }
+
+    #[test]
+    fn dispatches_commands_from_backend_events() {
+        let backend = FakeBackend::default();
+        let bindings = [
+            binding("STT", "Ctrl+B", HotkeyAction::Press(HotkeyCommand::ToggleSTT)),
+            binding(
+                "Push-to-talk",
+                "Ctrl+Space",
+                HotkeyAction::Hold { start: HotkeyCommand::PushToTalkStart, stop: HotkeyCommand::PushToTalkStop },
+            ),
+        ];
+        let (handle, errors, received) = start(&backend, &bindings);
+        assert!(errors.is_empty());
+        backend.fire("Ctrl+B", KeyEvent::Pressed);
+        backend.fire("Ctrl+B", KeyEvent::Released);
+        backend.fire("Ctrl+Space", KeyEvent::Pressed);
+        backend.fire("Ctrl+Space", KeyEvent::Released);
+        assert_eq!(
+            *received.lock().unwrap(),
+            [HotkeyCommand::ToggleSTT, HotkeyCommand::PushToTalkStart, HotkeyCommand::PushToTalkStop]
+        );
+        handle.stop();
+        assert!(backend.stopped.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn reports_invalid_and_rejected_bindings() {
+        let backend = FakeBackend { rejected: Some("R".to_owned()), ..Default::default() };
+        let bindings = [
+            binding("Stop speech", "Ctrl+Nope", HotkeyAction::Press(HotkeyCommand::StopSpeech)),
+            binding("Regenerate last reply", "Ctrl+R", HotkeyAction::Press(HotkeyCommand::Regenerate)),
+            binding("Toggle TTS", "Ctrl+T", HotkeyAction::Press(HotkeyCommand::ToggleTTS)),
+        ];
+        let (_handle, errors, received) = start(&backend, &bindings);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("Invalid Stop speech hotkey"));
+        assert!(errors[1].starts_with("Could not register hotkey Ctrl+R"));
+        backend.fire("Ctrl+T", KeyEvent::Pressed);
+        assert_eq!(*received.lock().unwrap(), [HotkeyCommand::ToggleTTS]);
+    }
+
+    #[test]
+    fn command_hotkeys_skip_unbound_entries() {
+        let hotkeys = CommandHotkeys { stop_speech: "Ctrl+Shift+S".to_owned(), regenerate: " ".to_owned(), ..Default::default() };
+        let bindings = hotkeys.bindings();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].action, HotkeyAction::Press(HotkeyCommand::StopSpeech));
+    }
+}
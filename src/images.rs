@@ -0,0 +1,89 @@
+// Image uploads prepared for vision models: EXIF orientation applied, scaled down to fit a
+// maximum edge without changing the aspect ratio, and re-encoded in the chosen format.
+use image::{
+    DynamicImage, ImageDecoder, ImageReader,
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    metadata::Orientation,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// Larger files are refused before decoding.
+pub const MAX_IMAGE_FILE_BYTES: u64 = 20 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ImageOutput {
+    #[default]
+    Png,
+    Jpeg,
+    WebP,
+}
+
+impl ImageOutput {
+    pub const ALL: [ImageOutput; 3] = [Self::Png, Self::Jpeg, Self::WebP];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Png => "PNG",
+            Self::Jpeg => "JPEG",
+            // The encoder only writes lossless WebP.
+            Self::WebP => "WebP (lossless)",
+        }
+    }
+
+    fn mime(&self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::WebP => "image/webp",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageOptions {
+    // Zero keeps the original size.
+    pub max_edge: u32,
+    pub format: ImageOutput,
+    pub jpeg_quality: u8,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self { max_edge: 1024, format: ImageOutput::Png, jpeg_quality: 85 }
+    }
+}
+
+// Returns the encoded bytes and their MIME type.
+pub fn prepare_image(path: &Path, options: &ImageOptions) -> Result<(Vec<u8>, &'static str), String> {
+    let size = path.metadata().map_err(|e| e.to_string())?.len();
+    if size > MAX_IMAGE_FILE_BYTES {
+        return Err(format!("image file too large ({} MB, limit {} MB)", size / (1024 * 1024), MAX_IMAGE_FILE_BYTES / (1024 * 1024)));
+    }
+    let mut decoder = ImageReader::open(path)
+        .map_err(|e| e.to_string())?
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .into_decoder()
+        .map_err(|e| e.to_string())?;
+    // Phone photos are stored sideways with an EXIF tag saying how to turn them.
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut img = DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+    img.apply_orientation(orientation);
+    if options.max_edge > 0 && img.width().max(img.height()) > options.max_edge {
+        img = img.resize(options.max_edge, options.max_edge, FilterType::Lanczos3);
+    }
+
+    let mut buffer = Vec::new();
+    let encoded = match options.format {
+        ImageOutput::Png => img.write_with_encoder(PngEncoder::new(&mut buffer)),
+        // Neither JPEG nor the WebP encoder take every pixel format, so convert first.
+        ImageOutput::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut buffer, options.jpeg_quality.clamp(1, 100))),
+        ImageOutput::WebP => DynamicImage::ImageRgba8(img.to_rgba8()).write_with_encoder(WebPEncoder::new_lossless(&mut buffer)),
+    };
+    encoded.map_err(|e| e.to_string())?;
+    Ok((buffer, options.format.mime()))
+}
@@ -0,0 +1,469 @@
+// Model management through LM Studio's local REST API, falling back to the `lms` CLI.
+// Nothing here panics: every failure comes back to the UI as a ModelEvent.
+//
+// The endpoints are LM Studio's v1 REST API, added in LM Studio 0.4.0:
+//   GET  /api/v1/models         {"models": [{"key", "size_bytes", "loaded_instances": [{"id", "config": {"context_length"}}]}]}
+//   POST /api/v1/models/load    {"model", "context_length"} -> {"load_time_seconds", ...}
+//   POST /api/v1/models/unload  {"instance_id"}
+// Older servers answer 404 there; loading and unloading then go straight to `lms`, and the list
+// reports that the server is too old. The REST load reports no progress, only `lms` does.
+use eframe::egui;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{process::Stdio, sync::mpsc, time::Instant};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    process::Command,
+};
+
+static PERCENT: Lazy<Regex> = Lazy::new(|| Regex::new(r"(\d+(?:\.\d+)?)\s*%").unwrap());
+// How much of `lms` stderr is kept for the error message.
+const STDERR_TAIL_BYTES: usize = 4096;
+
+#[derive(Clone)]
+pub struct LoadedModel {
+    pub model: String,
+    pub instance_id: String,
+    pub size_bytes: Option<u64>,
+    pub context_length: Option<u64>,
+}
+
+// Remembered per model in settings. Zero or empty means LM Studio's default.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoadOptions {
+    pub context_length: u32,
+    pub gpu_offload: Option<f32>,
+    pub ttl_secs: u64,
+    pub identifier: String,
+}
+
+impl LoadOptions {
+    // The REST load endpoint only takes the context length; the rest needs `lms`.
+    fn needs_cli(&self) -> bool {
+        self.gpu_offload.is_some() || self.ttl_secs > 0 || !self.identifier.trim().is_empty()
+    }
+
+    fn rest_body(&self, model: &str) -> Value {
+        let mut body = json!({ "model": model });
+        if self.context_length > 0 {
+            body["context_length"] = json!(self.context_length);
+        }
+        body
+    }
+
+    fn lms_args(&self, model: &str) -> Vec<String> {
+        let mut args = vec!["load".to_owned(), model.to_owned(), "--yes".to_owned()];
+        if self.context_length > 0 {
+            args.extend(["--context-length".to_owned(), self.context_length.to_string()]);
+        }
+        if let Some(ratio) = self.gpu_offload {
+            let gpu = match ratio {
+                r if r <= 0.0 => "off".to_owned(),
+                r if r >= 1.0 => "max".to_owned(),
+                r => format!("{:.2}", r),
+            };
+            args.extend(["--gpu".to_owned(), gpu]);
+        }
+        if self.ttl_secs > 0 {
+            args.extend(["--ttl".to_owned(), self.ttl_secs.to_string()]);
+        }
+        if !self.identifier.trim().is_empty() {
+            args.extend(["--identifier".to_owned(), self.identifier.trim().to_owned()]);
+        }
+        args
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModelAction {
+    Load,
+    Unload,
+}
+
+impl ModelAction {
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Self::Load => "load",
+            Self::Unload => "unload",
+        }
+    }
+}
+
+pub enum ModelEvent {
+    Progress { model: String, fraction: f32 },
+    Loaded { model: String, secs: f32, warning: Option<String> },
+    Unloaded { model: String },
+    // Instances this app loaded; only these are unloaded by the idle timeout.
+    Owned(Vec<String>),
+    Failed { model: String, action: ModelAction, error: String },
+    Listed(Result<Vec<LoadedModel>, String>),
+}
+
+// Wakes the UI after every event, so model status shows up without polling.
+#[derive(Clone)]
+pub struct ModelEventSender {
+    pub tx: mpsc::Sender<ModelEvent>,
+    pub ctx: egui::Context,
+}
+
+impl ModelEventSender {
+    pub fn send(&self, event: ModelEvent) {
+        let _ = self.tx.send(event);
+        self.ctx.request_repaint();
+    }
+}
+
+// The chat completions URL points at the same server, e.g. http://localhost:1234/v1/chat/completions.
+pub fn server_root(api_url: &str) -> String {
+    reqwest::Url::parse(api_url)
+        .map(|url| url.origin().ascii_serialization())
+        .unwrap_or_else(|_| "http://localhost:1234".to_owned())
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+    const MB: f64 = 1024.0 * 1024.0;
+    if bytes as f64 >= GB {
+        format!("{:.1} GB", bytes as f64 / GB)
+    } else {
+        format!("{:.0} MB", bytes as f64 / MB)
+    }
+}
+
+const TOO_OLD: &str = "this LM Studio has no model API (needs LM Studio 0.4.0 or newer)";
+
+// A REST call either failed outright or hit a server without the v1 API, in which case only
+// the `lms` result is worth reporting.
+#[derive(Debug, PartialEq)]
+enum RestError {
+    Unsupported,
+    Failed(String),
+}
+
+impl RestError {
+    fn message(self) -> String {
+        match self {
+            Self::Unsupported => TOO_OLD.to_owned(),
+            Self::Failed(message) => message,
+        }
+    }
+}
+
+fn rest_error(status: StatusCode, body: &Value) -> RestError {
+    if status == StatusCode::NOT_FOUND || status == StatusCode::METHOD_NOT_ALLOWED {
+        return RestError::Unsupported;
+    }
+    let message = body["error"]["message"].as_str().or(body["error"].as_str()).unwrap_or_default();
+    RestError::Failed(format!("{} {}", status, message).trim().to_owned())
+}
+
+async fn post_json(client: &Client, url: &str, body: Value) -> Result<Value, RestError> {
+    let response = client.post(url).json(&body).send().await.map_err(|e| RestError::Failed(e.to_string()))?;
+    let status = response.status();
+    let value: Value = response.json().await.unwrap_or(Value::Null);
+    if status.is_success() {
+        Ok(value)
+    } else {
+        Err(rest_error(status, &value))
+    }
+}
+
+async fn rest_list(client: &Client, root: &str) -> Result<Vec<LoadedModel>, RestError> {
+    let response = client
+        .get(format!("{}/api/v1/models", root))
+        .send()
+        .await
+        .map_err(|e| RestError::Failed(format!("LM Studio is not reachable at {}: {}", root, e)))?;
+    let status = response.status();
+    let value: Value = response.json().await.unwrap_or(Value::Null);
+    if !status.is_success() {
+        return Err(match rest_error(status, &value) {
+            RestError::Failed(message) => RestError::Failed(format!("LM Studio model list failed: {}", message)),
+            unsupported => unsupported,
+        });
+    }
+    parse_loaded(&value).map_err(RestError::Failed)
+}
+
+pub async fn list_loaded(client: &Client, root: &str) -> Result<Vec<LoadedModel>, String> {
+    rest_list(client, root).await.map_err(RestError::message)
+}
+
+// One entry per loaded instance; a model loaded twice shows up twice.
+fn parse_loaded(value: &Value) -> Result<Vec<LoadedModel>, String> {
+    let models = value["models"].as_array().ok_or("unexpected model list from LM Studio")?;
+    let mut loaded = Vec::new();
+    for model in models {
+        for instance in model["loaded_instances"].as_array().into_iter().flatten() {
+            loaded.push(LoadedModel {
+                model: model["key"].as_str().unwrap_or_default().to_owned(),
+                instance_id: instance["id"].as_str().unwrap_or_default().to_owned(),
+                size_bytes: model["size_bytes"].as_u64(),
+                context_length: instance["config"]["context_length"].as_u64(),
+            });
+        }
+    }
+    Ok(loaded)
+}
+
+fn parse_load_time(value: &Value) -> Option<f32> {
+    value["load_time_seconds"].as_f64().map(|secs| secs as f32)
+}
+
+async fn rest_load(client: &Client, root: &str, model: &str, options: &LoadOptions) -> Result<f32, RestError> {
+    let started = Instant::now();
+    let value = post_json(client, &format!("{}/api/v1/models/load", root), options.rest_body(model)).await?;
+    Ok(parse_load_time(&value).unwrap_or_else(|| started.elapsed().as_secs_f32()))
+}
+
+async fn rest_unload(client: &Client, root: &str, model: &str) -> Result<(), RestError> {
+    let instances: Vec<LoadedModel> = rest_list(client, root)
+        .await?
+        .into_iter()
+        .filter(|loaded| loaded.model == model || loaded.instance_id == model)
+        .collect();
+    if instances.is_empty() {
+        return Err(RestError::Failed(format!("{} is not loaded", model)));
+    }
+    for loaded in instances {
+        post_json(client, &format!("{}/api/v1/models/unload", root), json!({ "instance_id": loaded.instance_id })).await?;
+    }
+    Ok(())
+}
+
+// The error shown when both paths failed; a server without the API adds nothing to the `lms` error.
+fn fallback_error(rest_error: RestError, lms_error: String) -> String {
+    match rest_error {
+        RestError::Unsupported => format!("{}; {}", lms_error, TOO_OLD),
+        RestError::Failed(message) => format!("{}; {}", message, lms_error),
+    }
+}
+
+// Runs `lms <args>`, forwarding any percentage it prints as load progress. Both pipes are read
+// at the same time: `lms` draws its progress bar on stderr, and a full pipe would stall it.
+async fn run_lms<S: AsRef<str>>(args: &[S], model: &str, events: &ModelEventSender) -> Result<(), String> {
+    let mut child = Command::new("lms")
+        .args(args.iter().map(|arg| arg.as_ref()))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => "the `lms` CLI is not on PATH".to_owned(),
+            _ => format!("could not start `lms`: {}", e),
+        })?;
+    let (_, stderr) = tokio::join!(
+        read_progress(child.stdout.take(), model, events),
+        read_progress(child.stderr.take(), model, events)
+    );
+    let status = child.wait().await.map_err(|e| e.to_string())?;
+    if status.success() {
+        Ok(())
+    } else {
+        let reason = stderr.rsplit(['\n', '\r']).find(|line| !line.trim().is_empty()).unwrap_or_default().trim();
+        Err(format!("`lms {}` failed ({}) {}", args[0].as_ref(), status, reason).trim().to_owned())
+    }
+}
+
+// Reads a pipe to the end, sending progress for every percentage seen, and returns its last few KB.
+async fn read_progress(stream: Option<impl AsyncRead + Unpin>, model: &str, events: &ModelEventSender) -> String {
+    let Some(mut stream) = stream else {
+        return String::new();
+    };
+    let mut buffer = [0u8; 512];
+    let mut tail = String::new();
+    while let Ok(read) = stream.read(&mut buffer).await {
+        if read == 0 {
+            break;
+        }
+        let chunk = String::from_utf8_lossy(&buffer[..read]);
+        if let Some(percent) = PERCENT.captures_iter(&chunk).last().and_then(|c| c[1].parse::<f32>().ok()) {
+            events.send(ModelEvent::Progress { model: model.to_owned(), fraction: (percent / 100.0).clamp(0.0, 1.0) });
+        }
+        tail.push_str(&chunk);
+        if tail.len() > STDERR_TAIL_BYTES {
+            let mut cut = tail.len() - STDERR_TAIL_BYTES;
+            while !tail.is_char_boundary(cut) {
+                cut += 1;
+            }
+            tail.drain(..cut);
+        }
+    }
+    tail
+}
+
+pub async fn is_loaded(client: &Client, root: &str, model: &str) -> Result<bool, String> {
+    let loaded = list_loaded(client, root).await?;
+    Ok(loaded.iter().any(|loaded| loaded.model == model || loaded.instance_id == model))
+}
+
+// Returns the load time and a warning when some options could not be applied.
+pub async fn load(
+    client: &Client,
+    root: &str,
+    model: &str,
+    options: &LoadOptions,
+    events: &ModelEventSender,
+) -> Result<(f32, Option<String>), String> {
+    let before: Option<Vec<String>> = list_loaded(client, root)
+        .await
+        .ok()
+        .map(|loaded| loaded.into_iter().map(|loaded| loaded.instance_id).collect());
+    let result = load_with_fallback(client, root, model, options, events).await;
+    // Whatever appeared during the load is ours. Without a list from before, nothing is claimed.
+    if let (Ok(_), Some(before), Ok(after)) = (&result, before, list_loaded(client, root).await) {
+        let new: Vec<String> = after
+            .into_iter()
+            .map(|loaded| loaded.instance_id)
+            .filter(|id| !before.contains(id))
+            .collect();
+        if !new.is_empty() {
+            events.send(ModelEvent::Owned(new));
+        }
+    }
+    result
+}
+
+async fn load_with_fallback(
+    client: &Client,
+    root: &str,
+    model: &str,
+    options: &LoadOptions,
+    events: &ModelEventSender,
+) -> Result<(f32, Option<String>), String> {
+    let started = Instant::now();
+    if options.needs_cli() {
+        match run_lms(&options.lms_args(model), model, events).await {
+            Ok(()) => Ok((started.elapsed().as_secs_f32(), None)),
+            Err(lms_error) => match rest_load(client, root, model, options).await {
+                Ok(secs) => Ok((secs, Some(format!("GPU offload, TTL and identifier were not applied: {}", lms_error)))),
+                Err(rest_error) => Err(fallback_error(rest_error, lms_error)),
+            },
+        }
+    } else {
+        match rest_load(client, root, model, options).await {
+            Ok(secs) => Ok((secs, None)),
+            Err(rest_error) => run_lms(&options.lms_args(model), model, events)
+                .await
+                .map(|_| (started.elapsed().as_secs_f32(), None))
+                .map_err(|lms_error| fallback_error(rest_error, lms_error)),
+        }
+    }
+}
+
+pub async fn load_model(client: Client, root: String, model: String, options: LoadOptions, events: ModelEventSender) {
+    match load(&client, &root, &model, &options, &events).await {
+        Ok((secs, warning)) => events.send(ModelEvent::Loaded { model, secs, warning }),
+        Err(error) => events.send(ModelEvent::Failed { model, action: ModelAction::Load, error }),
+    }
+    events.send(ModelEvent::Listed(list_loaded(&client, &root).await));
+}
+
+pub async fn unload_model(client: Client, root: String, model: String, events: ModelEventSender) {
+    let result = match rest_unload(&client, &root, &model).await {
+        Ok(()) => Ok(()),
+        Err(rest_error) => run_lms(&["unload", &model], &model, &events)
+            .await
+            .map_err(|lms_error| fallback_error(rest_error, lms_error)),
+    };
+    match result {
+        Ok(()) => events.send(ModelEvent::Unloaded { model }),
+        Err(error) => events.send(ModelEvent::Failed { model, action: ModelAction::Unload, error }),
+    }
+    events.send(ModelEvent::Listed(list_loaded(&client, &root).await));
+}
+
+pub async fn refresh_loaded(client: Client, root: String, events: ModelEventSender) {
+    events.send(ModelEvent::Listed(list_loaded(&client, &root).await));
+}
+
+// Used by the idle timeout: unloads the given instances one after another. Models loaded by
+// other clients or by hand in LM Studio are never passed in here.
+pub async fn unload_instances(client: Client, root: String, instances: Vec<String>, events: ModelEventSender) {
+    for instance_id in instances {
+        let unload = json!({ "instance_id": instance_id });
+        let result = match post_json(&client, &format!("{}/api/v1/models/unload", root), unload).await {
+            Err(RestError::Unsupported) => run_lms(&["unload", &instance_id], &instance_id, &events).await,
+            other => other.map(|_| ()).map_err(RestError::message),
+        };
+        match result {
+            Ok(()) => events.send(ModelEvent::Unloaded { model: instance_id }),
+            Err(error) => events.send(ModelEvent::Failed { model: instance_id, action: ModelAction::Unload, error }),
+        }
+    }
+    events.send(ModelEvent::Listed(list_loaded(&client, &root).await));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_loaded_instances() {
+        let response = json!({
+            "models": [
+                {
+                    "type": "llm",
+                    "key": "qwen/qwen3-8b",
+                    "size_bytes": 5_030_000_000u64,
+                    "loaded_instances": [
+                        { "id": "qwen/qwen3-8b", "config": { "context_length": 8192 } },
+                        { "id": "qwen/qwen3-8b:2", "config": { "context_length": 32768 } }
+                    ]
+                },
+                { "type": "llm", "key": "google/gemma-3-4b", "size_bytes": 3_000_000_000u64, "loaded_instances": [] },
+                { "type": "embedding", "key": "nomic-embed-text" }
+            ]
+        });
+        let loaded = parse_loaded(&response).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].model, "qwen/qwen3-8b");
+        assert_eq!(loaded[0].instance_id, "qwen/qwen3-8b");
+        assert_eq!(loaded[0].size_bytes, Some(5_030_000_000));
+        assert_eq!(loaded[0].context_length, Some(8192));
+        assert_eq!(loaded[1].instance_id, "qwen/qwen3-8b:2");
+        assert_eq!(loaded[1].context_length, Some(32768));
+    }
+
+    #[test]
+    fn rejects_a_list_without_models() {
+        assert!(parse_loaded(&json!({ "data": [] })).is_err());
+        assert!(parse_loaded(&json!({ "models": [] })).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parses_load_time() {
+        let response = json!({ "type": "llm", "instance_id": "qwen/qwen3-8b", "load_time_seconds": 3.25, "status": "loaded" });
+        assert_eq!(parse_load_time(&response), Some(3.25));
+        assert_eq!(parse_load_time(&json!({ "status": "loaded" })), None);
+    }
+
+    #[test]
+    fn missing_endpoints_mean_an_older_server() {
+        assert_eq!(rest_error(StatusCode::NOT_FOUND, &Value::Null), RestError::Unsupported);
+        assert_eq!(rest_error(StatusCode::METHOD_NOT_ALLOWED, &Value::Null), RestError::Unsupported);
+        assert_eq!(
+            rest_error(StatusCode::BAD_REQUEST, &json!({ "error": { "message": "model not found" } })),
+            RestError::Failed("400 Bad Request model not found".to_owned())
+        );
+        assert_eq!(
+            rest_error(StatusCode::INTERNAL_SERVER_ERROR, &json!({ "error": "out of memory" })),
+            RestError::Failed("500 Internal Server Error out of memory".to_owned())
+        );
+    }
+
+    #[test]
+    fn fallback_error_skips_the_missing_api() {
+        assert_eq!(
+            fallback_error(RestError::Unsupported, "`lms load` failed".to_owned()),
+            format!("`lms load` failed; {}", TOO_OLD)
+        );
+        assert_eq!(
+            fallback_error(RestError::Failed("400 Bad Request".to_owned()), "`lms load` failed".to_owned()),
+            "400 Bad Request; `lms load` failed"
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use eframe::egui;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use hound;
-use std::{collections::{hash_map::DefaultHasher, HashMap},fs,hash::{Hash, Hasher},io::Read,process::{Command, Stdio},sync::{atomic::{AtomicBool, Ordering},mpsc::{self, SyncSender, sync_channel},Arc, Mutex},thread,time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+use std::{collections::{hash_map::DefaultHasher, HashMap},fs,hash::{Hash, Hasher},io::Read,sync::{atomic::{AtomicBool, Ordering},mpsc::{self, SyncSender, sync_channel},Arc, Mutex},thread,time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
@@ -17,23 +17,56 @@ use regex::Regex;
 use cmudict_fast::Cmudict;
 use once_cell::sync::Lazy;
 use syntect::{highlighting::ThemeSet, parsing::SyntaxSet, easy::HighlightLines};
-use simple_transcribe_rs::{transcriber::Transcriber, model_handler::ModelHandler};
-use futures::executor::block_on;
+use simple_transcribe_rs::model_handler::ModelHandler;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
 use rfd::FileDialog;
-use image::{ImageOutputFormat, imageops::FilterType};
-use base64::{engine::general_purpose, Engine};
-use crate::tts::{process_tts, AVAILABLE_VOICES};
-use win_hotkeys::{HotkeyManager, VKey, InterruptHandle};
+use crate::tts::{on_tts_finished, process_tts, tts_active, playback_level_db, AVAILABLE_VOICES};
+use crate::reasoning::{
+    ReasoningHistory, ReasoningSplitter, default_reasoning_tags, format_reasoning_tags, messages_for_model,
+    parse_reasoning_tags, split_reasoning,
+};
+use crate::vad::{BargeInDetector, VoiceActivityDetector};
+use crate::resample::{Resampler, downmix};
+use crate::stt::{
+    STT_LANGUAGES, SttOptions, WHISPER_MODEL_SIZES, decode_audio_file, heavy_transcribe, initial_prompt_from, live_transcribe,
+    preload_whisper, save_recording, transcribe_with_timestamps,
+};
+use crate::documents::extract_document_text;
+use crate::images::{ImageOptions, ImageOutput, prepare_image};
+use crate::folders::{FolderOptions, FolderScan, read_text_file, scan_folder};
+use crate::attachments::{Attachment, AttachmentKind, prune_blobs};
+use crate::lmstudio::{LoadOptions, LoadedModel, ModelAction, ModelEvent, ModelEventSender, format_bytes, server_root};
+use crate::hotkeys::{CommandHotkeys, HotkeyAction, HotkeyBinding, HotkeyCommand, HotkeyHandle};
 use crossbeam_channel::{unbounded, Receiver};
 pub mod heteronyms;
 pub mod tts;
 pub mod contractions;
+pub mod reasoning;
+pub mod lmstudio;
+pub mod vad;
+pub mod resample;
+pub mod stt;
+pub mod hotkeys;
+pub mod documents;
+pub mod images;
+pub mod messages;
+pub mod folders;
+pub mod attachments;
 const SETTINGS_FILE: &str = "settings.json";
 const MEMORY_FILE: &str = "memory.bin";
-const TEMP_AUDIO_FILE: &str = "temp_audio.wav";
+const RECORDINGS_DIR: &str = "recordings";
+const STT_SAMPLE_RATE: u32 = 16000;
+// Clips shorter than this after trimming are treated as empty.
+const MIN_CLIP_DURATION: Duration = Duration::from_millis(300);
+// Audio kept from just before a barge-in so the first word is not cut off.
+const BARGE_IN_PRE_ROLL: usize = STT_SAMPLE_RATE as usize / 2;
 const TTS_MODEL_PATH: &str = "onnx/modelv1.onnx";
 const TTS_CMU_DICT_PATH: &str = "cmudict.dict";
 const TTS_TOKENIZER_PATH: &str = "tokenizer.json";
+const MEMORY_SAVE_DEBOUNCE: Duration = Duration::from_millis(750);
+// History id of the system prompt, the only system message sent to the model.
+const SYSTEM_PROMPT_ID: &str = "system-0";
+const ESTIMATED_BUBBLE_HEIGHT: f32 = 40.0;
 static TTS_MODEL_LOADED: AtomicBool = AtomicBool::new(false);
 static SELECTED_VOICE_PATH: Lazy<Mutex<String>> = Lazy::new(|| Mutex::new("af_bella.bin".to_string()));
 #[derive(Clone, PartialEq)]
@@ -45,7 +78,7 @@ enum Sender {
 #[derive(Clone)]
 struct ChatBubble {
     content: String,
-    attachment_content: Option<String>,
+    attachment: Option<Arc<Attachment>>,
     sender: Sender,
     is_thinking: bool,
     is_code: bool,
@@ -53,13 +86,40 @@ struct ChatBubble {
     id: egui::Id,
     timestamp: Option<Instant>,
     persistent: bool,
+    message_id: egui::Id,
+    truncated: bool,
+    thinking_secs: Option<f32>,
 }
 enum BubbleMessage {
     New(ChatBubble),
     Update { id: egui::Id, content: String },
     Remove(egui::Id),
+    Append { message_id: egui::Id, content: String, truncated: bool },
+}
+// Background tasks send chat updates through this, and every send wakes the UI.
+#[derive(Clone)]
+struct BubbleSender {
+    tx: UnboundedSender<BubbleMessage>,
+    ctx: egui::Context,
+}
+impl BubbleSender {
+    fn send(&self, message: BubbleMessage) -> Result<(), tokio::sync::mpsc::error::SendError<BubbleMessage>> {
+        let result = self.tx.send(message);
+        self.ctx.request_repaint();
+        result
+    }
+}
+// The UI also has to notice the channel closing when the task ends. The sender is released
+// before the repaint is requested, so that frame already sees the channel closed.
+impl Drop for BubbleSender {
+    fn drop(&mut self) {
+        let (closed, _) = unbounded_channel();
+        drop(std::mem::replace(&mut self.tx, closed));
+        self.ctx.request_repaint();
+    }
 }
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 struct AppSettings {
     api_url: String,
     selected_model: String,
@@ -74,6 +134,26 @@ struct AppSettings {
     max_completion_tokens: u32,
     send_stt: bool,
     selected_voice: String,
+    reasoning_tags: Vec<(String, String)>,
+    reasoning_history: HashMap<String, ReasoningHistory>,
+    load_options: HashMap<String, LoadOptions>,
+    jit_load: bool,
+    idle_unload_mins: u32,
+    vad_enabled: bool,
+    vad_silence_ms: u32,
+    input_device: String,
+    keep_recordings: bool,
+    whisper_model: String,
+    stt_language: String,
+    stt_translate: bool,
+    stt_context_prompt: bool,
+    live_transcription: bool,
+    stt_hotkey: String,
+    push_to_talk: bool,
+    push_to_talk_hotkey: String,
+    command_hotkeys: CommandHotkeys,
+    image_options: ImageOptions,
+    folder_options: FolderOptions,
 }
 impl Default for AppSettings {
     fn default() -> Self {
@@ -91,6 +171,26 @@ impl Default for AppSettings {
             max_completion_tokens: 10024,
             send_stt: false,
             selected_voice: "af_bella.bin".to_owned(),
+            reasoning_tags: default_reasoning_tags(),
+            reasoning_history: HashMap::new(),
+            load_options: HashMap::new(),
+            jit_load: false,
+            idle_unload_mins: 0,
+            vad_enabled: true,
+            vad_silence_ms: 1500,
+            input_device: String::new(),
+            keep_recordings: false,
+            whisper_model: "small".to_owned(),
+            stt_language: "auto".to_owned(),
+            stt_translate: false,
+            stt_context_prompt: true,
+            live_transcription: true,
+            stt_hotkey: "Ctrl+B".to_owned(),
+            push_to_talk: false,
+            push_to_talk_hotkey: "Ctrl+Space".to_owned(),
+            command_hotkeys: CommandHotkeys::default(),
+            image_options: ImageOptions::default(),
+            folder_options: FolderOptions::default(),
         }
     }
 }
@@ -101,9 +201,47 @@ fn unique_id(prefix: &str, content: &str) -> egui::Id {
     let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
     egui::Id::new(format!("{}-{}-{}", prefix, now, hash))
 }
+// Written to a temporary file first so a crash mid-write never leaves a truncated memory file.
 fn save_memory(memory: &Vec<Value>) {
     if let Ok(serialized) = encode_to_vec(memory, config::standard()) {
-        let _ = fs::write(MEMORY_FILE, serialized);
+        let temp = format!("{}.tmp", MEMORY_FILE);
+        if fs::write(&temp, serialized).is_ok() {
+            let _ = fs::rename(&temp, MEMORY_FILE);
+        }
+    }
+}
+struct MemoryWrite {
+    memory: Vec<Value>,
+    // Signalled once this snapshot, or a newer one, is on disk.
+    done: Option<mpsc::Sender<()>>,
+}
+// All writes go through one thread so an older snapshot can never land after a newer one.
+static MEMORY_WRITER: Lazy<mpsc::Sender<MemoryWrite>> = Lazy::new(|| {
+    let (tx, rx) = mpsc::channel::<MemoryWrite>();
+    thread::spawn(move || {
+        while let Ok(mut write) = rx.recv() {
+            let mut waiting: Vec<mpsc::Sender<()>> = write.done.take().into_iter().collect();
+            // Only the newest snapshot matters if several queued up while writing.
+            while let Ok(newer) = rx.try_recv() {
+                write = newer;
+                waiting.extend(write.done.take());
+            }
+            save_memory(&write.memory);
+            for done in waiting {
+                let _ = done.send(());
+            }
+        }
+    });
+    tx
+});
+fn save_memory_in_background(memory: Vec<Value>) {
+    let _ = MEMORY_WRITER.send(MemoryWrite { memory, done: None });
+}
+// Queues the final snapshot behind any pending background write and waits until it is saved.
+fn save_memory_and_wait(memory: Vec<Value>) {
+    let (done_tx, done_rx) = mpsc::channel();
+    if MEMORY_WRITER.send(MemoryWrite { memory, done: Some(done_tx) }).is_ok() {
+        let _ = done_rx.recv();
     }
 }
 fn load_memory() -> Vec<Value> {
@@ -114,7 +252,7 @@ fn load_memory() -> Vec<Value> {
             return mem_vec;
         }
     }
-    vec![serde_json::json!({"role":"system", "content": "You are an AI companion.", "id":"system-0"})]
+    vec![serde_json::json!({"role":"system", "content": "You are an AI companion.", "id": SYSTEM_PROMPT_ID})]
 }
 fn save_app_settings(settings: &AppSettings) {
     if let Ok(json_str) = serde_json::to_string_pretty(settings) {
@@ -176,7 +314,7 @@ fn highlight_code_job(code: &str, language: Option<&str>) -> egui::text::LayoutJ
     }
     job
 }
-fn split_content_into_bubbles(sender: Sender, content: &str, is_thinking: bool) -> Vec<ChatBubble> {
+fn split_content_into_bubbles(sender: Sender, content: &str, is_thinking: bool, message_id: egui::Id) -> Vec<ChatBubble> {
     let mut bubbles = Vec::new();
     let mut remaining = content;
     while let Some(start) = remaining.find("```") {
@@ -185,13 +323,16 @@ fn split_content_into_bubbles(sender: Sender, content: &str, is_thinking: bool)
             bubbles.push(ChatBubble {
                 sender: sender.clone(),
                 content: text_before.to_string(),
-                attachment_content: None,
+                attachment: None,
                 is_thinking,
                 is_code: false,
                 language: None,
                 id: unique_id("bubble", text_before),
                 timestamp: None,
                 persistent: true,
+                message_id,
+                truncated: false,
+                thinking_secs: None,
             });
         }
         let after_ticks = &remaining[start + 3..];
@@ -212,13 +353,16 @@ fn split_content_into_bubbles(sender: Sender, content: &str, is_thinking: bool)
                 bubbles.push(ChatBubble {
                     sender: sender.clone(),
                     content: code_text.to_string(),
-                    attachment_content: None,
+                    attachment: None,
                     is_thinking,
                     is_code: true,
                     language,
                     id: unique_id("code", code_text),
                     timestamp: None,
                     persistent: true,
+                    message_id,
+                    truncated: false,
+                    thinking_secs: None,
                 });
             }
             remaining = &remaining[code_content_start + end + 3..];
@@ -226,13 +370,16 @@ fn split_content_into_bubbles(sender: Sender, content: &str, is_thinking: bool)
             bubbles.push(ChatBubble {
                 sender: sender.clone(),
                 content: remaining[code_content_start..].to_string(),
-                attachment_content: None,
+                attachment: None,
                 is_thinking,
                 is_code: true,
                 language,
                 id: unique_id("code", &remaining[code_content_start..]),
                 timestamp: None,
                 persistent: true,
+                message_id,
+                truncated: false,
+                thinking_secs: None,
             });
             remaining = "";
             break;
@@ -242,13 +389,108 @@ fn split_content_into_bubbles(sender: Sender, content: &str, is_thinking: bool)
         bubbles.push(ChatBubble {
             sender,
             content: remaining.to_string(),
-            attachment_content: None,
+            attachment: None,
             is_thinking,
             is_code: false,
             language: None,
             id: unique_id("bubble", remaining),
             timestamp: None,
             persistent: true,
+            message_id,
+            truncated: false,
+            thinking_secs: None,
+        });
+    }
+    bubbles
+}
+fn bubbles_from_history(history: &[Value]) -> Vec<ChatBubble> {
+    let mut bubbles = Vec::new();
+    for (index, entry) in history.iter().enumerate() {
+        // Older files stored the prompt under a hashed id, but it always came first.
+        let is_prompt = entry["role"] == "system" && (index == 0 || entry["id"] == SYSTEM_PROMPT_ID);
+        let message_id = match entry["id"].as_str() {
+            _ if is_prompt => egui::Id::new(SYSTEM_PROMPT_ID),
+            Some(id) => egui::Id::new(id),
+            None => unique_id("bubble", &entry["content"].to_string()),
+        };
+        let sender = match entry["role"].as_str() {
+            Some("assistant") => Sender::Model,
+            Some("system") => Sender::System,
+            _ => Sender::User,
+        };
+        if let Some(reasoning) = entry["reasoning"].as_str() {
+            bubbles.push(ChatBubble {
+                sender: sender.clone(),
+                content: reasoning.to_owned(),
+                attachment: None,
+                is_thinking: true,
+                is_code: false,
+                language: None,
+                id: unique_id("reasoning", reasoning),
+                timestamp: None,
+                persistent: false,
+                message_id,
+                truncated: false,
+                thinking_secs: entry["thinking_secs"].as_f64().map(|secs| secs as f32),
+            });
+        }
+        let mut attachments: Vec<Attachment> = match entry.get("attachments") {
+            Some(list) => serde_json::from_value(list.clone()).unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let content = match &entry["content"] {
+            Value::String(text) if sender == Sender::Model => {
+                bubbles.extend(split_content_into_bubbles(sender, text, false, message_id));
+                continue;
+            }
+            Value::String(text) => text.clone(),
+            Value::Array(parts) => {
+                // Older files kept images inline as data URLs; they move into the attachment store.
+                for (index, url) in parts.iter().filter_map(|p| p["image_url"]["url"].as_str()).enumerate() {
+                    match Attachment::from_data_url(url, format!("image-{}", index + 1)) {
+                        Ok(attachment) => attachments.push(attachment),
+                        Err(e) => eprintln!("[MEMORY] Dropping unreadable image: {}", e),
+                    }
+                }
+                parts.iter().find_map(|p| p["text"].as_str()).unwrap_or_default().to_owned()
+            }
+            _ => continue,
+        };
+        for attachment in attachments {
+            bubbles.push(ChatBubble {
+                sender: sender.clone(),
+                content: attachment.filename.clone(),
+                id: unique_id("attachment", &attachment.filename),
+                attachment: Some(Arc::new(attachment)),
+                is_thinking: false,
+                is_code: false,
+                language: None,
+                timestamp: None,
+                persistent: true,
+                message_id,
+                truncated: false,
+                thinking_secs: None,
+            });
+        }
+        // Messages sent with attachments only have no text bubble.
+        if content.is_empty() {
+            continue;
+        }
+        // Notices saved by older versions expire like fresh ones; the system prompt stays.
+        let timestamp = (sender == Sender::System && !is_prompt).then(Instant::now);
+        bubbles.push(ChatBubble {
+            sender,
+            content,
+            attachment: None,
+            is_thinking: false,
+            is_code: false,
+            language: None,
+            id: message_id,
+            timestamp,
+            persistent: true,
+            message_id,
+            truncated: false,
+            thinking_secs: None,
         });
     }
     bubbles
@@ -268,28 +510,158 @@ fn strip_code_blocks(text: &str) -> String {
     }
     result
 }
-fn send_bubbles(tx: &UnboundedSender<BubbleMessage>, sender: Sender, content: &str, is_thinking: bool) {
-    for bubble in split_content_into_bubbles(sender, content, is_thinking) {
+// One history entry per message; notices are UI-only and never stored or sent.
+fn history_from_bubbles(bubbles: &[ChatBubble]) -> Vec<Value> {
+    let mut new_history = Vec::new();
+    let mut seen_messages = std::collections::HashSet::new();
+    for bubble in bubbles {
+        // Code and text bubbles are joined back into markdown.
+        if bubble.persistent && seen_messages.insert(bubble.message_id) {
+            let role = match bubble.sender {
+                Sender::User => "user",
+                Sender::Model => "assistant",
+                Sender::System => "system",
+            };
+            let attachments: Vec<&Attachment> = bubbles
+                .iter()
+                .filter(|b| b.message_id == bubble.message_id)
+                .filter_map(|b| b.attachment.as_deref())
+                .collect();
+            let mut entry = json!({
+                "role": role,
+                "content": message_markdown(bubbles, bubble.message_id),
+                "id": format!("{:?}", bubble.message_id)
+            });
+            if bubble.message_id == egui::Id::new(SYSTEM_PROMPT_ID) {
+                entry["id"] = json!(SYSTEM_PROMPT_ID);
+            }
+            // Only metadata and blob keys; the model layer expands them when sending.
+            if !attachments.is_empty() {
+                entry["attachments"] = json!(attachments);
+            }
+            if let Some(reasoning) = bubbles.iter().find(|b| b.message_id == bubble.message_id && b.is_thinking)
+            {
+                entry["reasoning"] = json!(reasoning.content);
+                if let Some(secs) = reasoning.thinking_secs {
+                    entry["thinking_secs"] = json!(secs);
+                }
+            }
+            new_history.push(entry);
+        }
+    }
+    new_history
+}
+// A status or error line shown in the chat. Notices are not persistent, so they are never saved
+// or sent to the model. Expiring ones vanish after a second; the rest stay until the chat is cleared.
+fn notice_bubble(content: String, expires: bool) -> ChatBubble {
+    let id = unique_id("bubble", &content);
+    ChatBubble {
+        sender: Sender::System,
+        content,
+        attachment: None,
+        is_thinking: false,
+        is_code: false,
+        language: None,
+        id,
+        timestamp: expires.then(Instant::now),
+        persistent: false,
+        message_id: id,
+        truncated: false,
+        thinking_secs: None,
+    }
+}
+fn message_markdown(bubbles: &[ChatBubble], message_id: egui::Id) -> String {
+    let mut markdown = String::new();
+    // Attachments are kept alongside the text and only expanded when sent to the model.
+    for bubble in bubbles
+        .iter()
+        .filter(|b| b.message_id == message_id && !b.is_thinking && b.attachment.is_none())
+    {
+        if bubble.is_code {
+            markdown.push_str("```");
+            if let Some(ref lang) = bubble.language {
+                markdown.push_str(lang);
+                markdown.push('\n');
+            }
+            markdown.push_str(&bubble.content);
+            if !bubble.content.ends_with('\n') {
+                markdown.push('\n');
+            }
+            markdown.push_str("```");
+        } else {
+            markdown.push_str(&bubble.content);
+        }
+    }
+    markdown.trim().to_string()
+}
+fn send_bubbles(
+    tx: &BubbleSender,
+    sender: Sender,
+    content: &str,
+    is_thinking: bool,
+    message_id: egui::Id,
+    truncated: bool,
+) {
+    for mut bubble in split_content_into_bubbles(sender, content, is_thinking, message_id) {
+        bubble.truncated = truncated;
         let _ = tx.send(BubbleMessage::New(bubble));
     }
 }
-fn send_reasoning(tx: &UnboundedSender<BubbleMessage>, sender: Sender, reasoning: &str) {
+fn send_reasoning(
+    tx: &BubbleSender,
+    sender: Sender,
+    reasoning: &str,
+    message_id: egui::Id,
+    thinking_secs: Option<f32>,
+) {
     if reasoning.trim().is_empty() {
         return;
     }
     let bubble = ChatBubble {
         sender,
         content: reasoning.to_owned(),
-        attachment_content: None,
+        attachment: None,
         is_thinking: true,
         is_code: false,
         language: None,
         id: unique_id("reasoning", reasoning),
         timestamp: None,
         persistent: false,
+        message_id,
+        truncated: false,
+        thinking_secs,
     };
     let _ = tx.send(BubbleMessage::New(bubble));
 }
+// Loads the model before a request when it is not resident yet, showing the wait in the chat.
+async fn ensure_model_loaded(
+    client: &Client,
+    root: &str,
+    model: &str,
+    options: &LoadOptions,
+    tx: &BubbleSender,
+    events: &ModelEventSender,
+) -> bool {
+    match lmstudio::is_loaded(client, root, model).await {
+        Ok(false) => {}
+        // Already resident, or the server is unreachable and the request will report it.
+        Ok(true) | Err(_) => return true,
+    }
+    let loading = notice_bubble(format!("Loading model {}...", model), false);
+    let loading_id = loading.id;
+    let _ = tx.send(BubbleMessage::New(loading));
+    let result = lmstudio::load(client, root, model, options, events).await;
+    events.send(ModelEvent::Listed(lmstudio::list_loaded(client, root).await));
+    let _ = tx.send(BubbleMessage::Remove(loading_id));
+    match result {
+        Ok(_) => true,
+        Err(error) => {
+            let failed = notice_bubble(format!("Failed to load model {}: {}", model, error), true);
+            let _ = tx.send(BubbleMessage::New(failed));
+            false
+        }
+    }
+}
 async fn call_model(
     client: &Client,
     api_url: &str,
@@ -317,23 +689,29 @@ async fn call_model(
     match client.post(api_url).json(&payload).send().await {
         Ok(response) => match response.json::<Value>().await {
             Ok(json_resp) => {
-                let content = json_resp["choices"][0]["message"]["content"]
-                    .as_str()
-                    .unwrap_or("(No valid response received)")
-                    .to_owned();
+                let content = json_resp["choices"][0]["message"]["content"].as_str();
+                let failed = content.is_none();
+                let content = content.unwrap_or("(No valid response received)").to_owned();
                 let reasoning = json_resp["choices"][0]["message"]["reasoning_content"]
                     .as_str()
                     .map(|s| s.to_owned());
-                ModelResponse { content, reasoning }
+                let finish_reason = json_resp["choices"][0]["finish_reason"]
+                    .as_str()
+                    .map(|s| s.to_owned());
+                ModelResponse { content, reasoning, finish_reason, failed }
             }
             Err(_) => ModelResponse {
                 content: "(Failed to parse response)".to_owned(),
                 reasoning: None,
+                finish_reason: None,
+                failed: true,
             },
         },
         Err(err) => ModelResponse {
             content: format!("Request failed: {}", err),
             reasoning: None,
+            finish_reason: None,
+            failed: true,
         },
     }
 }
@@ -349,10 +727,11 @@ async fn call_model_streaming(
     top_k: u32,
     repeat_penalty: f32,
     max_completion_tokens: u32,
-    tx: UnboundedSender<BubbleMessage>,
+    tx: BubbleSender,
     tts_enabled: Arc<AtomicBool>,
     tts_stop_flag: Arc<AtomicBool>,
     history_arc: Arc<Mutex<Vec<Value>>>,
+    reasoning_tags: Vec<(String, String)>,
 ) {
     let payload = json!({
         "model": model,
@@ -369,16 +748,20 @@ async fn call_model_streaming(
     let mut response = match client.post(api_url).json(&payload).send().await {
         Ok(resp) => resp,
         Err(err) => {
+            let id = unique_id("bubble", "");
             let _ = tx.send(BubbleMessage::New(ChatBubble {
                 sender: Sender::Model,
                 content: format!("Request failed: {}", err),
-                attachment_content: None,
+                attachment: None,
                 is_thinking: false,
                 is_code: false,
                 language: None,
-                id: unique_id("bubble", ""),
+                id,
                 timestamp: None,
                 persistent: true,
+                message_id: id,
+                truncated: false,
+                thinking_secs: None,
             }));
             return;
         }
@@ -389,6 +772,9 @@ async fn call_model_streaming(
     let reasoning_bubble_id = unique_id("stream_reasoning", "");
     let mut content_created = false;
     let mut reasoning_created = false;
+    let mut finish_reason: Option<String> = None;
+    let mut splitter = ReasoningSplitter::new(reasoning_tags);
+    let mut reasoning_window: Option<(Instant, Instant)> = None;
     let mut leftover = String::new();
 
     // CRITICAL FIX: Properly handle Server-Sent Events (SSE) format
@@ -418,22 +804,43 @@ async fn call_model_streaming(
             if let Ok(json_val) = serde_json::from_str::<Value>(&data) {
                 if let Some(choices) = json_val.get("choices").and_then(|c| c.as_array()) {
                     for choice in choices {
+                        if let Some(reason) = choice.get("finish_reason").and_then(|r| r.as_str()) {
+                            finish_reason = Some(reason.to_owned());
+                        }
                         if let Some(delta) = choice.get("delta") {
+                            // Tagged reasoning inside `content` is routed the same way as `reasoning_content`
+                            let mut new_reasoning = delta
+                                .get("reasoning_content")
+                                .and_then(|r| r.as_str())
+                                .unwrap_or_default()
+                                .to_owned();
+                            let mut new_content = String::new();
+                            if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
+                                let split = splitter.push(content);
+                                new_reasoning.push_str(&split.reasoning);
+                                new_content = split.content;
+                            }
+
                             // Process reasoning content if present
-                            if let Some(reasoning) = delta.get("reasoning_content").and_then(|r| r.as_str()) {
-                                accumulated_reasoning.push_str(reasoning);
+                            if !new_reasoning.is_empty() {
+                                let now = Instant::now();
+                                reasoning_window = Some((reasoning_window.map_or(now, |(start, _)| start), now));
+                                accumulated_reasoning.push_str(&new_reasoning);
                                 if !accumulated_reasoning.trim().is_empty() {
                                     if !reasoning_created {
                                         let _ = tx.send(BubbleMessage::New(ChatBubble {
                                             sender: Sender::Model,
                                             content: accumulated_reasoning.trim().to_owned(),
-                                            attachment_content: None,
+                                            attachment: None,
                                             is_thinking: true,
                                             is_code: false,
                                             language: None,
                                             id: reasoning_bubble_id,
                                             timestamp: None,
                                             persistent: false,
+                                            message_id: content_bubble_id,
+                                            truncated: false,
+                                            thinking_secs: None,
                                         }));
                                         reasoning_created = true;
                                     } else {
@@ -446,20 +853,23 @@ async fn call_model_streaming(
                             }
 
                             // Process content if present
-                            if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                accumulated_content.push_str(content);
+                            if !new_content.is_empty() {
+                                accumulated_content.push_str(&new_content);
                                 if !accumulated_content.trim().is_empty() {
                                     if !content_created {
                                         let _ = tx.send(BubbleMessage::New(ChatBubble {
                                             sender: Sender::Model,
                                             content: accumulated_content.trim().to_owned(),
-                                            attachment_content: None,
+                                            attachment: None,
                                             is_thinking: false,
                                             is_code: false,
                                             language: None,
                                             id: content_bubble_id,
                                             timestamp: None,
                                             persistent: true,
+                                            message_id: content_bubble_id,
+                                            truncated: false,
+                                            thinking_secs: None,
                                         }));
                                         content_created = true;
                                     } else {
@@ -491,9 +901,14 @@ async fn call_model_streaming(
             if let Ok(json_val) = serde_json::from_str::<Value>(&data) {
                 if let Some(choices) = json_val.get("choices").and_then(|c| c.as_array()) {
                     for choice in choices {
+                        if let Some(reason) = choice.get("finish_reason").and_then(|r| r.as_str()) {
+                            finish_reason = Some(reason.to_owned());
+                        }
                         if let Some(delta) = choice.get("delta") {
                             if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                accumulated_content.push_str(content);
+                                let split = splitter.push(content);
+                                accumulated_content.push_str(&split.content);
+                                accumulated_reasoning.push_str(&split.reasoning);
                             }
                         }
                     }
@@ -503,16 +918,21 @@ async fn call_model_streaming(
     }
 
     // Clean up and finalize
+    let rest = splitter.finish();
+    accumulated_content.push_str(&rest.content);
+    accumulated_reasoning.push_str(&rest.reasoning);
     accumulated_content = accumulated_content.trim_start().to_owned();
     let _ = tx.send(BubbleMessage::Remove(content_bubble_id));
     let _ = tx.send(BubbleMessage::Remove(reasoning_bubble_id));
 
     if !accumulated_reasoning.trim().is_empty() {
-        send_reasoning(&tx, Sender::Model, accumulated_reasoning.trim());
+        let thinking_secs = reasoning_window.map(|(start, end)| (end - start).as_secs_f32());
+        send_reasoning(&tx, Sender::Model, accumulated_reasoning.trim(), content_bubble_id, thinking_secs);
     }
 
     if !accumulated_content.trim().is_empty() {
-        send_bubbles(&tx, Sender::Model, accumulated_content.trim(), false);
+        let truncated = finish_reason.as_deref() == Some("length");
+        send_bubbles(&tx, Sender::Model, accumulated_content.trim(), false, content_bubble_id, truncated);
 
         // Save to history
         let mut history = history_arc.lock().unwrap();
@@ -521,54 +941,153 @@ async fn call_model_streaming(
             "content": accumulated_content.trim(),
             "id": format!("{:?}", content_bubble_id)
         }));
-        save_memory(&*history);
 
         // Process TTS
         process_tts(&accumulated_content, &tts_enabled, tts_stop_flag);
     }
 }
+// Streams a continuation onto the end of an existing reply and returns the text it added.
+async fn stream_continuation(
+    client: &Client,
+    api_url: &str,
+    payload: &Value,
+    message_id: egui::Id,
+    reasoning_tags: Vec<(String, String)>,
+    tx: &BubbleSender,
+) -> Result<String, String> {
+    let mut response = client.post(api_url).json(payload).send().await.map_err(|e| format!("Request failed: {}", e))?;
+    let mut splitter = ReasoningSplitter::new(reasoning_tags);
+    let mut continuation = String::new();
+    let mut finish_reason: Option<String> = None;
+    let mut leftover = String::new();
+    let mut finished = false;
+    while !finished {
+        match response.chunk().await {
+            Ok(Some(chunk)) => leftover.push_str(&String::from_utf8_lossy(&chunk)),
+            // The last event may not end with a blank line.
+            _ => {
+                leftover.push_str("\n\n");
+                finished = true;
+            }
+        }
+        while let Some(pos) = leftover.find("\n\n") {
+            let event: String = leftover.drain(..pos + 2).collect();
+            let Some(data) = event.lines().find_map(|line| line.strip_prefix("data: ")) else {
+                continue;
+            };
+            let Ok(json_val) = serde_json::from_str::<Value>(data) else {
+                continue;
+            };
+            for choice in json_val["choices"].as_array().into_iter().flatten() {
+                if let Some(reason) = choice["finish_reason"].as_str() {
+                    finish_reason = Some(reason.to_owned());
+                }
+                // Reasoning is not shown for continuations, only the answer text.
+                if let Some(content) = choice["delta"]["content"].as_str() {
+                    let split = splitter.push(content);
+                    if !split.content.is_empty() {
+                        continuation.push_str(&split.content);
+                        let _ = tx.send(BubbleMessage::Append { message_id, content: split.content, truncated: false });
+                    }
+                }
+            }
+        }
+    }
+    let rest = splitter.finish().content;
+    continuation.push_str(&rest);
+    if continuation.is_empty() && finish_reason.is_none() {
+        return Err("(No valid response received)".to_owned());
+    }
+    let truncated = finish_reason.as_deref() == Some("length");
+    let _ = tx.send(BubbleMessage::Append { message_id, content: rest, truncated });
+    Ok(continuation)
+}
 struct ModelResponse {
     content: String,
     reasoning: Option<String>,
+    finish_reason: Option<String>,
+    // Set when content is an error description rather than the model's words.
+    failed: bool,
 }
-fn load_model(selected_model: &str) {
-    Command::new("lms")
-        .arg("load")
-        .arg(selected_model)
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("Failed to load model");
+// A load or unload request that LM Studio has not answered yet.
+struct ModelTask {
+    model: String,
+    action: ModelAction,
+    started: Instant,
+    fraction: Option<f32>,
 }
-fn unload_model(selected_model: &str) {
-    Command::new("lms")
-        .arg("unload")
-        .arg(selected_model)
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("Failed to unload model");
+// Captures any cpal sample type and hands it on as f32 in [-1, 1].
+fn build_capture_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut on_samples: impl FnMut(&[f32]) + Send + 'static,
+    on_error: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let samples: Vec<f32> = data.iter().map(|&s| s.to_sample::<f32>()).collect();
+            on_samples(&samples);
+        },
+        on_error,
+        None,
+    )
 }
-fn heavy_transcribe(
-    _stt_writer: Option<Arc<Mutex<Option<hound::WavWriter<std::io::BufWriter<fs::File>>>>>>,
-) -> Result<String, String> {
-    eprintln!("heavy_transcribe: Starting transcription...");
-    let rt = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
-    let join_handle = rt.spawn_blocking(move || {
-        let model_handler = block_on(ModelHandler::new("small", "models/")); // tiny, base, small, medium, large.
-        let transcriber = Transcriber::new(model_handler);
-        transcriber.transcribe(TEMP_AUDIO_FILE, None).map_err(|e| e.to_string())
-    });
-    let result = rt.block_on(join_handle).map_err(|e| e.to_string())??;
-    eprintln!("heavy_transcribe: Removing temporary file...");
-    fs::remove_file(TEMP_AUDIO_FILE).map_err(|e| e.to_string())?;
-    let text = result.get_text().to_string();
-    eprintln!("heavy_transcribe: Transcribed text: {}", text);
-    Ok(text)
+fn input_device_names() -> Vec<String> {
+    match cpal::default_host().input_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            eprintln!("[AUDIO] Could not list input devices: {}", e);
+            Vec::new()
+        }
+    }
+}
+// Hands-free conversation: listen, transcribe, answer, speak, listen again.
+#[derive(Clone, Copy, PartialEq)]
+enum ConversationState {
+    Off,
+    Listening,
+    Thinking,
+    Speaking,
 }
-enum HotkeyCommand {
-    ToggleSTT,
+// Binds the global hotkeys on the platform's backend. Problems come back as messages for the chat.
+fn start_global_hotkeys(
+    toggle: &str,
+    push_to_talk: Option<&str>,
+    commands: &CommandHotkeys,
+    ctx: &egui::Context,
+) -> (HotkeyHandle, Receiver<HotkeyCommand>, Vec<String>) {
+    let (hotkey_tx, hotkey_rx) = unbounded();
+    let mut bindings = vec![HotkeyBinding {
+        name: "STT",
+        hotkey: toggle.to_owned(),
+        action: HotkeyAction::Press(HotkeyCommand::ToggleSTT),
+    }];
+    if let Some(push_to_talk) = push_to_talk {
+        bindings.push(HotkeyBinding {
+            name: "push-to-talk",
+            hotkey: push_to_talk.to_owned(),
+            action: HotkeyAction::Hold { start: HotkeyCommand::PushToTalkStart, stop: HotkeyCommand::PushToTalkStop },
+        });
+    }
+    bindings.extend(commands.bindings());
+    let (backend, unavailable) = hotkeys::platform_backend();
+    let hotkey_ctx = ctx.clone();
+    let (handle, mut errors) = hotkeys::start_hotkeys(backend, &bindings, move |command| {
+        let _ = hotkey_tx.send(command);
+        hotkey_ctx.request_repaint();
+    });
+    errors.extend(unavailable);
+    (handle, hotkey_rx, errors)
 }
 struct ChatApp {
     input_text: String,
+    // Added since the last send; they join the chat with the next message.
+    pending_attachments: Vec<Arc<Attachment>>,
     chat_bubbles: Vec<ChatBubble>,
     conversation_history: Arc<Mutex<Vec<Value>>>,
     client: Client,
@@ -585,59 +1104,109 @@ struct ChatApp {
     streaming_enabled: bool,
     tts_stop_flag: Arc<AtomicBool>,
     experimental_reasoning: bool,
+    reasoning_tags: Vec<(String, String)>,
+    temp_reasoning_tags: String,
+    reasoning_history: HashMap<String, ReasoningHistory>,
+    load_options: HashMap<String, LoadOptions>,
+    load_dialog: Option<(String, LoadOptions)>,
+    jit_load: bool,
+    idle_unload_mins: u32,
+    last_model_use: Instant,
     scroll_to_bottom: bool,
     show_settings: bool,
     temp_api_url: String,
     new_model_name: String,
     conversation_channels: Vec<UnboundedReceiver<BubbleMessage>>,
-    editing_bubble: Option<usize>,
+    // Keyed by bubble id; indices shift when bubbles are deleted or a reply is regenerated.
+    editing_bubble: Option<egui::Id>,
     input_panel_height: f32,
     default_settings: AppSettings,
     code_layout_cache: HashMap<egui::Id, egui::text::LayoutJob>,
     stt_recording: bool,
     stt_active: Arc<AtomicBool>,
+    vad_enabled: bool,
+    vad_silence_ms: u32,
+    vad_stop: Arc<AtomicBool>,
+    input_device: String,
+    input_devices: Vec<String>,
+    keep_recordings: bool,
+    whisper_model: String,
+    stt_language: String,
+    stt_translate: bool,
+    stt_context_prompt: bool,
+    live_transcription: bool,
+    stt_hotkey: String,
+    push_to_talk: bool,
+    push_to_talk_hotkey: String,
+    temp_stt_hotkey: String,
+    temp_push_to_talk_hotkey: String,
+    command_hotkeys: CommandHotkeys,
+    temp_command_hotkeys: CommandHotkeys,
+    image_options: ImageOptions,
+    folder_options: FolderOptions,
+    folder_dialog: Option<FolderScan>,
+    // Folder being scanned in the background; a result for any other folder is stale.
+    folder_scanning: Option<std::path::PathBuf>,
+    folder_scan_tx: mpsc::Sender<(std::path::PathBuf, Result<FolderScan, String>)>,
+    folder_scan_rx: mpsc::Receiver<(std::path::PathBuf, Result<FolderScan, String>)>,
+    ptt_recording: bool,
+    stt_transcribing: bool,
+    conversation: ConversationState,
+    barge_in: Arc<AtomicBool>,
+    audio_error_tx: mpsc::Sender<String>,
+    audio_error_rx: mpsc::Receiver<String>,
     stt_stream: Option<cpal::Stream>,
     audio_sample_tx: Option<SyncSender<Vec<i16>>>,
-    audio_thread_handle: Option<thread::JoinHandle<()>>,
+    audio_thread_handle: Option<thread::JoinHandle<Vec<i16>>>,
     send_stt: bool,
-    transcription_tx: mpsc::Sender<String>,
-    transcription_rx: mpsc::Receiver<String>,
-    last_repaint: Instant,
+    transcription_tx: mpsc::Sender<Result<String, String>>,
+    transcription_rx: mpsc::Receiver<Result<String, String>>,
+    partial_tx: mpsc::Sender<String>,
+    partial_rx: mpsc::Receiver<String>,
+    audio_upload_tx: mpsc::Sender<(String, Result<String, String>)>,
+    audio_upload_rx: mpsc::Receiver<(String, Result<String, String>)>,
+    audio_uploads_pending: usize,
     selected_voice: String,
-    hotkey_interrupt_handle: Option<InterruptHandle>,
+    hotkey_handle: Option<HotkeyHandle>,
     hotkey_rx: Receiver<HotkeyCommand>,
-    background_repaint_timer: Instant,
     shutting_down: bool,
-    force_repaint_counter: u32,
+    ctx: egui::Context,
+    history_dirty: bool,
+    memory_save_due: Option<Instant>,
+    bubble_heights: HashMap<egui::Id, f32>,
+    model_events: ModelEventSender,
+    model_events_rx: mpsc::Receiver<ModelEvent>,
+    model_task: Option<ModelTask>,
+    model_status: Option<String>,
+    loaded_models: Result<Vec<LoadedModel>, String>,
+    owned_instances: std::collections::HashSet<String>,
 }
 impl ChatApp {
-    fn new() -> Self {
+    fn new(ctx: egui::Context) -> Self {
         let settings = load_app_settings();
+        let memory = load_memory();
         let (tx, rx) = mpsc::channel();
+        let (model_events_tx, model_events_rx) = mpsc::channel();
+        let (audio_error_tx, audio_error_rx) = mpsc::channel();
+        let (partial_tx, partial_rx) = mpsc::channel();
+        let (audio_upload_tx, audio_upload_rx) = mpsc::channel();
+        let (folder_scan_tx, folder_scan_rx) = mpsc::channel();
+        let model_events = ModelEventSender { tx: model_events_tx, ctx: ctx.clone() };
+        // Conversation mode moves on from Speaking when playback ends.
+        let tts_ctx = ctx.clone();
+        on_tts_finished(move || tts_ctx.request_repaint());
+        tokio::spawn(lmstudio::refresh_loaded(Client::new(), server_root(&settings.api_url), model_events.clone()));
         let selected_voice = settings.selected_voice.clone();
         *SELECTED_VOICE_PATH.lock().unwrap() = selected_voice.clone();
 
-        let mut manager = HotkeyManager::new();
-
-        let (hotkey_tx, hotkey_rx) = unbounded();
-
-        manager.register_channel(hotkey_tx);
-
-        manager.register_hotkey(VKey::B, &[VKey::Control], || {
-            println!("[HOTKEY] Ctrl+B pressed!");
-            HotkeyCommand::ToggleSTT
-        }).unwrap();
-
-        let interrupt_handle = manager.interrupt_handle();
-
-        thread::spawn(move || {
-            manager.event_loop();
-        });
+        let push_to_talk = settings.push_to_talk.then_some(settings.push_to_talk_hotkey.as_str());
+        let (hotkey_handle, hotkey_rx, hotkey_errors) = start_global_hotkeys(&settings.stt_hotkey, push_to_talk, &settings.command_hotkeys, &ctx);
 
-        Self {
+        let mut app = Self {
             input_text: String::new(),
-            chat_bubbles: Vec::new(),
-            conversation_history: Arc::new(Mutex::new(load_memory())),
+            pending_attachments: Vec::new(),
+            chat_bubbles: bubbles_from_history(&memory),
+            conversation_history: Arc::new(Mutex::new(memory)),
             client: Client::new(),
             api_url: settings.api_url.clone(),
             selected_model: settings.selected_model.clone(),
@@ -652,6 +1221,14 @@ impl ChatApp {
             streaming_enabled: settings.streaming_enabled,
             tts_stop_flag: Arc::new(AtomicBool::new(false)),
             experimental_reasoning: true,
+            reasoning_tags: settings.reasoning_tags.clone(),
+            temp_reasoning_tags: format_reasoning_tags(&settings.reasoning_tags),
+            reasoning_history: settings.reasoning_history.clone(),
+            load_options: settings.load_options.clone(),
+            load_dialog: None,
+            jit_load: settings.jit_load,
+            idle_unload_mins: settings.idle_unload_mins,
+            last_model_use: Instant::now(),
             scroll_to_bottom: false,
             show_settings: false,
             temp_api_url: settings.api_url.clone(),
@@ -663,93 +1240,215 @@ impl ChatApp {
             code_layout_cache: HashMap::new(),
             stt_recording: false,
             stt_active: Arc::new(AtomicBool::new(false)),
+            vad_enabled: settings.vad_enabled,
+            vad_silence_ms: settings.vad_silence_ms,
+            vad_stop: Arc::new(AtomicBool::new(false)),
+            input_device: settings.input_device.clone(),
+            input_devices: Vec::new(),
+            keep_recordings: settings.keep_recordings,
+            whisper_model: settings.whisper_model.clone(),
+            stt_language: settings.stt_language.clone(),
+            stt_translate: settings.stt_translate,
+            stt_context_prompt: settings.stt_context_prompt,
+            live_transcription: settings.live_transcription,
+            stt_hotkey: settings.stt_hotkey.clone(),
+            push_to_talk: settings.push_to_talk,
+            push_to_talk_hotkey: settings.push_to_talk_hotkey.clone(),
+            temp_stt_hotkey: settings.stt_hotkey.clone(),
+            temp_push_to_talk_hotkey: settings.push_to_talk_hotkey.clone(),
+            command_hotkeys: settings.command_hotkeys.clone(),
+            temp_command_hotkeys: settings.command_hotkeys.clone(),
+            image_options: settings.image_options.clone(),
+            folder_options: settings.folder_options.clone(),
+            folder_dialog: None,
+            folder_scanning: None,
+            folder_scan_tx,
+            folder_scan_rx,
+            ptt_recording: false,
+            stt_transcribing: false,
+            conversation: ConversationState::Off,
+            barge_in: Arc::new(AtomicBool::new(false)),
+            audio_error_tx,
+            audio_error_rx,
             stt_stream: None,
             audio_sample_tx: None,
             audio_thread_handle: None,
             send_stt: settings.send_stt,
             transcription_tx: tx,
             transcription_rx: rx,
-            last_repaint: Instant::now(),
+            partial_tx,
+            partial_rx,
+            audio_upload_tx,
+            audio_upload_rx,
+            audio_uploads_pending: 0,
             selected_voice,
-            hotkey_interrupt_handle: Some(interrupt_handle),
+            hotkey_handle: Some(hotkey_handle),
             hotkey_rx,
-            background_repaint_timer: Instant::now(),
             shutting_down: false,
-            force_repaint_counter: 0,
+            ctx,
+            // Restored bubbles get fresh ids, so the history is rebuilt to match them.
+            history_dirty: true,
+            memory_save_due: None,
+            bubble_heights: HashMap::new(),
+            model_events,
+            model_events_rx,
+            model_task: None,
+            model_status: None,
+            loaded_models: Ok(Vec::new()),
+            owned_instances: std::collections::HashSet::new(),
+        };
+        for error in hotkey_errors {
+            app.push_system_notice(error, false);
         }
+        app
     }
 
-    fn start_stt_recording(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.stt_active.store(true, Ordering::Relaxed);
+    fn restart_hotkeys(&mut self) {
+        if let Some(handle) = self.hotkey_handle.take() {
+            handle.stop();
+        }
+        let push_to_talk = self.push_to_talk.then_some(self.push_to_talk_hotkey.as_str());
+        let (hotkey_handle, hotkey_rx, errors) = start_global_hotkeys(&self.stt_hotkey, push_to_talk, &self.command_hotkeys, &self.ctx);
+        self.hotkey_handle = Some(hotkey_handle);
+        self.hotkey_rx = hotkey_rx;
+        for error in errors {
+            self.push_system_notice(error, false);
+        }
+    }
 
+    fn start_stt_recording(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let host = cpal::default_host();
-        let device = host.default_input_device().ok_or("No input device available")?;
+        let device = if self.input_device.is_empty() {
+            host.default_input_device().ok_or("No input device available")?
+        } else {
+            host.input_devices()?
+                .find(|device| device.name().is_ok_and(|name| name == self.input_device))
+                .ok_or_else(|| format!("Input device \"{}\" is not connected", self.input_device))?
+        };
         println!("[AUDIO] Using device: {}", device.name().unwrap_or_else(|_| "Unknown".to_string()));
 
         // Get the default input config
         let config = device.default_input_config()?;
 
-        // Create the audio stream with proper error handling for background operation
-        let err_fn = move |err: cpal::StreamError| {
-            eprintln!("[AUDIO] Stream error: {}", err);
+        let target_rate = STT_SAMPLE_RATE;
+        let channels = config.channels() as usize;
+        let mut resampler = Resampler::new(config.sample_rate().0, target_rate);
+
+        let (sample_tx, sample_rx): (SyncSender<Vec<i16>>, _) = sync_channel(256);
+
+        let stt_active = self.stt_active.clone();
+        let sample_tx_clone = sample_tx.clone();
+        // Conversation mode needs the detector to know when a turn ends.
+        let conversing = self.conversation != ConversationState::Off;
+        let mut vad = (self.vad_enabled || conversing).then(|| VoiceActivityDetector::new(target_rate, self.vad_silence_ms));
+        self.vad_stop.store(false, Ordering::Relaxed);
+        let vad_stop = self.vad_stop.clone();
+        let vad_ctx = self.ctx.clone();
+        let mut barge_in = conversing.then(|| BargeInDetector::new(target_rate));
+        let mut mic_open = !conversing;
+        let mut pre_roll: Vec<i16> = Vec::new();
+        self.barge_in.store(false, Ordering::Relaxed);
+        let barge_in_flag = self.barge_in.clone();
+        let on_samples = move |samples: &[f32]| {
+            if !stt_active.load(Ordering::Relaxed) {
+                return;
+            }
+            let mut downsampled: Vec<i16> = resampler
+                .process(&downmix(samples, channels))
+                .into_iter()
+                .map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+                .collect();
+            // While the app is speaking only a barge-in opens the microphone, so its own
+            // voice coming back through the mic is never recorded.
+            if !mic_open {
+                match barge_in.as_mut() {
+                    Some(detector) if tts_active() => {
+                        pre_roll.extend_from_slice(&downsampled);
+                        let excess = pre_roll.len().saturating_sub(BARGE_IN_PRE_ROLL);
+                        pre_roll.drain(..excess);
+                        if !detector.push(&downsampled, playback_level_db()) {
+                            return;
+                        }
+                        barge_in_flag.store(true, Ordering::Relaxed);
+                        vad_ctx.request_repaint();
+                        downsampled = std::mem::take(&mut pre_roll);
+                    }
+                    _ => {}
+                }
+                mic_open = true;
+            }
+            if let Some(vad) = vad.as_mut() {
+                vad.push(&downsampled);
+                if vad.should_stop() && !vad_stop.swap(true, Ordering::Relaxed) {
+                    vad_ctx.request_repaint();
+                }
+            }
+            let _ = sample_tx_clone.try_send(downsampled);
         };
 
-        let target_rate = 16000;
-        let channels = config.channels() as usize;
-        let factor = (config.sample_rate().0 as f32 / target_rate as f32).round() as usize;
-        let factor = if factor < 1 { 1 } else { factor };
-
-        let spec = hound::WavSpec {
-            channels: 1,
-            sample_rate: target_rate,
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
+        // Stream errors (an unplugged microphone, for one) are reported in the chat.
+        let error_tx = self.audio_error_tx.clone();
+        let error_ctx = self.ctx.clone();
+        let on_error = move |err: cpal::StreamError| {
+            eprintln!("[AUDIO] Stream error: {}", err);
+            let message = match err {
+                cpal::StreamError::DeviceNotAvailable => "The microphone was disconnected.".to_owned(),
+                other => format!("Microphone error: {}", other),
+            };
+            let _ = error_tx.send(message);
+            error_ctx.request_repaint();
         };
 
-        let _writer = hound::WavWriter::create(TEMP_AUDIO_FILE, spec)?;
-        let (sample_tx, sample_rx): (SyncSender<Vec<i16>>, _) = sync_channel(256);
-        self.audio_sample_tx = Some(sample_tx);
+        let stream_config: cpal::StreamConfig = config.clone().into();
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::I8 => build_capture_stream::<i8>(&device, &stream_config, on_samples, on_error),
+            cpal::SampleFormat::I16 => build_capture_stream::<i16>(&device, &stream_config, on_samples, on_error),
+            cpal::SampleFormat::I32 => build_capture_stream::<i32>(&device, &stream_config, on_samples, on_error),
+            cpal::SampleFormat::I64 => build_capture_stream::<i64>(&device, &stream_config, on_samples, on_error),
+            cpal::SampleFormat::U8 => build_capture_stream::<u8>(&device, &stream_config, on_samples, on_error),
+            cpal::SampleFormat::U16 => build_capture_stream::<u16>(&device, &stream_config, on_samples, on_error),
+            cpal::SampleFormat::U32 => build_capture_stream::<u32>(&device, &stream_config, on_samples, on_error),
+            cpal::SampleFormat::U64 => build_capture_stream::<u64>(&device, &stream_config, on_samples, on_error),
+            cpal::SampleFormat::F32 => build_capture_stream::<f32>(&device, &stream_config, on_samples, on_error),
+            cpal::SampleFormat::F64 => build_capture_stream::<f64>(&device, &stream_config, on_samples, on_error),
+            other => return Err(format!("Unsupported input sample format: {}", other).into()),
+        }?;
 
-        let spec_clone = spec;
+        let vad_enabled = self.vad_enabled;
+        let live_samples = self.live_transcription.then(|| Arc::new(Mutex::new(Vec::new())));
+        let audio_live_samples = live_samples.clone();
+        // Collects the clip in memory; it is handed back when the recording is stopped.
         let audio_thread = thread::spawn(move || {
-            let mut writer = hound::WavWriter::create(TEMP_AUDIO_FILE, spec_clone)
-                .expect("Failed to create WAV writer");
+            let mut recorded = Vec::new();
             while let Ok(samples) = sample_rx.recv() {
-                for sample in samples {
-                    if let Err(e) = writer.write_sample(sample) {
-                        eprintln!("Error writing sample: {:?}", e);
-                    }
+                if let Some(live) = &audio_live_samples {
+                    live.lock().unwrap().extend_from_slice(&samples);
                 }
+                recorded.extend(samples);
             }
-            if let Err(e) = writer.finalize() {
-                eprintln!("Error finalizing WAV writer: {:?}", e);
+            if vad_enabled {
+                vad::trim_silence(&recorded, target_rate).to_vec()
+            } else {
+                recorded
             }
         });
         self.audio_thread_handle = Some(audio_thread);
+        self.audio_sample_tx = Some(sample_tx);
 
-        let stt_active = self.stt_active.clone();
-        let sample_tx_clone = self.audio_sample_tx.as_ref().unwrap().clone();
-
-        let stream = device.build_input_stream(
-            &config.into(),
-            move | data: &[i16], _: &cpal::InputCallbackInfo| {
-                if !stt_active.load(Ordering::Relaxed) {
-                    return;
-                }
-                let mut downsampled = Vec::with_capacity(data.len() / factor);
-                for (i, frame) in data.chunks(channels).enumerate() {
-                    if i % factor == 0 {
-                        if let Some(&s) = frame.get(0) {
-                            downsampled.push(s);
-                        }
-                    }
-                }
-                let _ = sample_tx_clone.try_send(downsampled);
-            },
-            err_fn,
-            None,
-        )?;
-
+        preload_whisper(self.whisper_model.clone());
+        self.stt_active.store(true, Ordering::Relaxed);
+        if let Some(live) = live_samples {
+            let active = self.stt_active.clone();
+            let options = self.stt_options();
+            let partial_tx = self.partial_tx.clone();
+            let partial_ctx = self.ctx.clone();
+            thread::spawn(move || {
+                live_transcribe(live, active, options, |partial| {
+                    let _ = partial_tx.send(partial);
+                    partial_ctx.request_repaint();
+                })
+            });
+        }
         stream.play()?;
         self.stt_stream = Some(stream);
         self.stt_recording = true;
@@ -758,58 +1457,82 @@ impl ChatApp {
         Ok(())
     }
 
-    fn stop_stt_recording(&mut self) {
+    fn stop_stt_recording(&mut self) -> Vec<i16> {
         self.stt_active.store(false, Ordering::Relaxed);
         self.stt_stream = None;
         self.audio_sample_tx = None;
-        if let Some(handle) = self.audio_thread_handle.take() {
-            let _ = handle.join();
-        }
+        let clip = match self.audio_thread_handle.take() {
+            Some(handle) => handle.join().unwrap_or_default(),
+            None => Vec::new(),
+        };
         self.stt_recording = false;
         println!("[STT] Recording stopped");
+        clip
     }
 
     fn stop_stt_recording_and_transcribe_heavy(&mut self) {
-        self.stop_stt_recording();
-
-        // Check if we actually have audio data before transcribing
-        let has_audio_data = if let Ok(metadata) = fs::metadata(TEMP_AUDIO_FILE) {
-            metadata.len() > 100 // WAV header is ~44 bytes, so >100 means actual audio data
-        } else {
-            false
-        };
+        let clip = self.stop_stt_recording();
 
-        if !has_audio_data {
-            println!("[STT] No audio data received, skipping transcription");
+        let duration = Duration::from_secs_f32(clip.len() as f32 / STT_SAMPLE_RATE as f32);
+        if duration < MIN_CLIP_DURATION {
             return;
         }
 
-        let tx_clone = self.transcription_tx.clone();
+        let tx = self.transcription_tx.clone();
+        let ctx = self.ctx.clone();
+        let keep_recordings = self.keep_recordings;
+        self.stt_transcribing = true;
+        let options = self.stt_options();
         tokio::spawn(async move {
-            let transcription_result =
-                tokio::task::spawn_blocking(move || heavy_transcribe(None)).await;
-            match transcription_result {
-                Ok(Ok(text)) => {
-                    tx_clone.send(text).expect("Failed to send transcription");
-                }
-                Ok(Err(e)) => {
-                    let _ = tx_clone.send(format!("Transcription error: {}", e));
-                }
-                Err(e) => {
-                    let _ = tx_clone.send(format!("Transcription join error: {:?}", e));
+            let transcription_result = tokio::task::spawn_blocking(move || {
+                let result = heavy_transcribe(&clip, &options);
+                if let (true, Ok(text)) = (keep_recordings, &result) {
+                    if let Err(e) = save_recording(&clip, text) {
+                        eprintln!("[STT] Could not save recording: {}", e);
+                    }
                 }
-            }
+                result
+            })
+            .await;
+            let _ = tx.send(transcription_result.unwrap_or_else(|e| Err(e.to_string())));
+            ctx.request_repaint();
         });
     }
 
+    fn stt_options(&self) -> SttOptions {
+        let initial_prompt = if self.stt_context_prompt {
+            let recent: Vec<&str> = self
+                .chat_bubbles
+                .iter()
+                .rev()
+                .filter(|b| b.sender != Sender::System && !b.is_thinking && !b.is_code)
+                .take(6)
+                .map(|b| b.content.as_str())
+                .collect();
+            initial_prompt_from(&recent.into_iter().rev().collect::<Vec<_>>().join(" "))
+        } else {
+            String::new()
+        };
+        SttOptions {
+            model_size: self.whisper_model.clone(),
+            language: self.stt_language.clone(),
+            translate: self.stt_translate,
+            initial_prompt,
+        }
+    }
+
     fn handle_file_upload(&mut self) {
-        let allowed_extensions = [
-            "plaintext", "docx", "pdf", "rs", "toml", "png", "jpeg", "jpg", "webp", "gif",
+        let known_extensions = [
+            "txt", "md", "docx", "pdf", "rs", "toml", "png", "jpeg", "jpg", "webp", "gif", "wav", "flac", "ogg",
         ];
-        if let Some(path) = FileDialog::new()
-            .add_filter("Allowed files", &allowed_extensions)
-            .pick_file()
-        {
+        // Several files can be picked at once; everything attached before sending goes into that message.
+        // Anything that is not an image, audio or document is attached as text unless it looks binary.
+        let paths = FileDialog::new()
+            .add_filter("Supported files", &known_extensions)
+            .add_filter("All files", &["*"])
+            .pick_files()
+            .unwrap_or_default();
+        for path in paths {
             let filename = path
                 .file_name()
                 .unwrap_or_default()
@@ -819,71 +1542,77 @@ impl ChatApp {
                 .extension()
                 .map(|e| e.to_string_lossy().to_lowercase())
                 .unwrap_or_default();
-            let header = format!("[Upload: {}]", filename);
-            if allowed_extensions.contains(&ext.as_str()) {
-                if ["png", "jpeg", "jpg", "webp", "gif"].contains(&ext.as_str()) {
-                    if let Ok(metadata) = path.metadata() {
-                        if metadata.len() > 20 * 1024 * 1024 {
-                            self.input_text.push_str("[Error: Image file too large (>20MB)]");
-                            return;
-                        }
-                    }
-                    match image::open(&path) {
-                        Ok(img) => {
-                            let resized = img.resize_exact(512, 512, FilterType::Lanczos3);
-                            let mut buffer = Vec::new();
-                            if resized.write_to(&mut std::io::Cursor::new(&mut buffer), ImageOutputFormat::Png).is_ok() {
-                                let encoded = general_purpose::STANDARD.encode(&buffer);
-                                let data_url = format!("data:image/png;base64,{}", encoded);
-                                self.input_text.push_str(&header);
-                                self.chat_bubbles.push(ChatBubble {
-                                    sender: Sender::User,
-                                    content: header.clone(),
-                                    attachment_content: Some(data_url),
-                                    is_thinking: false,
-                                    is_code: false,
-                                    language: None,
-                                    id: unique_id("attachment", &header),
-                                    timestamp: Some(Instant::now()),
-                                    persistent: true,
-                                });
-                            }
-                        }
-                        Err(e) => {
-                            self.input_text.push_str(&format!("[Error: Failed to open image file: {}]", e));
-                        }
-                    }
-                } else {
-                    match fs::read(&path) {
-                        Ok(bytes) => {
-                            let full_text = match String::from_utf8(bytes.clone()) {
-                                Ok(text) => text,
-                                Err(_) => general_purpose::STANDARD.encode(&bytes),
-                            };
-                            self.input_text.push_str(&header);
-                            self.chat_bubbles.push(ChatBubble {
-                                sender: Sender::User,
-                                content: header.clone(),
-                                attachment_content: Some(full_text),
-                                is_thinking: false,
-                                is_code: false,
-                                language: None,
-                                id: unique_id("attachment", &header),
-                                timestamp: Some(Instant::now()),
-                                persistent: true,
-                            });
-                        }
-                        Err(e) => {
-                            self.input_text.push_str(&format!("[Error: Failed to read file: {}]", e));
-                        }
-                    }
+            if ["wav", "flac", "ogg"].contains(&ext.as_str()) {
+                self.transcribe_audio_file(path, filename);
+            } else if ["png", "jpeg", "jpg", "webp", "gif"].contains(&ext.as_str()) {
+                match prepare_image(&path, &self.image_options) {
+                    Ok((bytes, mime)) => self.push_attachment(AttachmentKind::Image, filename, mime, bytes),
+                    Err(e) => self.push_system_notice(format!("Could not attach image {}: {}", filename, e), false),
+                }
+            } else if ["pdf", "docx"].contains(&ext.as_str()) {
+                match extract_document_text(&path) {
+                    Ok(text) => self.push_attachment(AttachmentKind::Document, filename, "text/plain", text.into_bytes()),
+                    Err(e) => self.push_system_notice(format!("Could not extract text from {}: {}", filename, e), false),
                 }
             } else {
-                self.input_text.push_str("[Error: File type not supported]");
+                match read_text_file(&path) {
+                    Ok(text) => self.push_attachment(AttachmentKind::Text, filename, "text/plain", text.into_bytes()),
+                    Err(e) => self.push_system_notice(format!("Could not attach {}: {}", filename, e), false),
+                }
             }
         }
     }
 
+    fn pick_folder(&mut self) {
+        let Some(root) = FileDialog::new().pick_folder() else {
+            return;
+        };
+        self.folder_dialog = None;
+        self.scan_folder_in_background(root);
+    }
+
+    fn scan_folder_in_background(&mut self, root: std::path::PathBuf) {
+        let options = self.folder_options.clone();
+        let tx = self.folder_scan_tx.clone();
+        let ctx = self.ctx.clone();
+        self.folder_scanning = Some(root.clone());
+        tokio::task::spawn_blocking(move || {
+            let result = scan_folder(&root, &options);
+            let _ = tx.send((root, result));
+            ctx.request_repaint();
+        });
+    }
+
+    fn push_attachment(&mut self, kind: AttachmentKind, filename: String, mime: &str, bytes: Vec<u8>) {
+        let attachment = match Attachment::new(kind, filename, mime, bytes) {
+            Ok(attachment) => attachment,
+            Err(e) => {
+                self.push_system_notice(format!("Could not attach file: {}", e), false);
+                return;
+            }
+        };
+        self.pending_attachments.push(Arc::new(attachment));
+    }
+
+    // Decoding and Whisper both take a while on long recordings, so the transcript is
+    // attached from the background once it is ready.
+    fn transcribe_audio_file(&mut self, path: std::path::PathBuf, filename: String) {
+        let mut options = self.stt_options();
+        options.initial_prompt.clear();
+        let tx = self.audio_upload_tx.clone();
+        let ctx = self.ctx.clone();
+        self.audio_uploads_pending += 1;
+        tokio::task::spawn_blocking(move || {
+            let result = decode_audio_file(&path).and_then(|samples| {
+                let secs = samples.len() / STT_SAMPLE_RATE as usize;
+                let transcript = transcribe_with_timestamps(&samples, &options)?;
+                Ok(format!("Length: {}:{:02}\n{}", secs / 60, secs % 60, transcript))
+            });
+            let _ = tx.send((filename, result));
+            ctx.request_repaint();
+        });
+    }
+
     fn save_settings(&self) {
         let updated_settings = AppSettings {
             api_url: self.api_url.clone(),
@@ -899,6 +1628,26 @@ impl ChatApp {
             max_completion_tokens: self.max_completion_tokens,
             send_stt: self.send_stt,
             selected_voice: self.selected_voice.clone(),
+            reasoning_tags: self.reasoning_tags.clone(),
+            reasoning_history: self.reasoning_history.clone(),
+            load_options: self.load_options.clone(),
+            jit_load: self.jit_load,
+            idle_unload_mins: self.idle_unload_mins,
+            vad_enabled: self.vad_enabled,
+            vad_silence_ms: self.vad_silence_ms,
+            input_device: self.input_device.clone(),
+            keep_recordings: self.keep_recordings,
+            whisper_model: self.whisper_model.clone(),
+            stt_language: self.stt_language.clone(),
+            stt_translate: self.stt_translate,
+            stt_context_prompt: self.stt_context_prompt,
+            live_transcription: self.live_transcription,
+            stt_hotkey: self.stt_hotkey.clone(),
+            push_to_talk: self.push_to_talk,
+            push_to_talk_hotkey: self.push_to_talk_hotkey.clone(),
+            command_hotkeys: self.command_hotkeys.clone(),
+            image_options: self.image_options.clone(),
+            folder_options: self.folder_options.clone(),
         };
         save_app_settings(&updated_settings);
     }
@@ -906,93 +1655,90 @@ impl ChatApp {
     fn clear_history(&mut self) {
         self.chat_bubbles.clear();
         self.code_layout_cache.clear();
-        let mut history = self.conversation_history.lock().unwrap();
-        history.clear();
-        save_memory(&*history);
-    }
-
-    fn rebuild_conversation_history(&self) {
-        let mut new_history = Vec::new();
-        for bubble in &self.chat_bubbles {
-            if bubble.persistent {
-                let role = match bubble.sender {
-                    Sender::User => "user",
-                    Sender::Model => "assistant",
-                    Sender::System => "system",
-                };
-                let full_content = if let Some(ref attach) = bubble.attachment_content {
-                    if attach.starts_with("data:image") {
-                        json!([
-                            { "type": "input_text", "text": bubble.content },
-                            { "type": "input_image", "image_url": { "url": attach } }
-                        ])
-                    } else {
-                        json!(format!("{}{}", bubble.content, attach))
-                    }
-                } else {
-                    json!(bubble.content.clone())
-                };
-                new_history.push(json!({
-                    "role": role,
-                    "content": full_content,
-                    "id": format!("{:?}", bubble.id)
-                }));
-            }
+        self.bubble_heights.clear();
+        self.conversation_history.lock().unwrap().clear();
+        self.history_dirty = true;
+    }
+
+    fn rebuild_conversation_history(&mut self) {
+        let new_history = history_from_bubbles(&self.chat_bubbles);
+        *self.conversation_history.lock().unwrap() = new_history;
+        let live_ids: std::collections::HashSet<egui::Id> = self.chat_bubbles.iter().map(|b| b.id).collect();
+        self.bubble_heights.retain(|id, _| live_ids.contains(id));
+        self.history_dirty = false;
+        self.memory_save_due = Some(Instant::now() + MEMORY_SAVE_DEBOUNCE);
+    }
+
+    fn flush_memory_if_due(&mut self) {
+        if self.memory_save_due.is_some_and(|due| Instant::now() >= due) {
+            self.memory_save_due = None;
+            save_memory_in_background(self.conversation_history.lock().unwrap().clone());
         }
-        let mut history = self.conversation_history.lock().unwrap();
-        *history = new_history;
-        save_memory(&*history);
     }
 
     fn process_input(&mut self) {
         let trimmed = self.input_text.trim();
-        if trimmed.is_empty() {
+        // Attachments can be sent without any text.
+        if trimmed.is_empty() && (self.editing_bubble.is_some() || self.pending_attachments.is_empty()) {
             return;
         }
-        if let Some(edit_index) = self.editing_bubble.take() {
-            let bubble = &mut self.chat_bubbles[edit_index];
-            bubble.content = trimmed.to_string();
-            {
-                let mut history = self.conversation_history.lock().unwrap();
-                for item in history.iter_mut() {
-                    if let Some(id_value) = item.get("id").and_then(|v| v.as_str()) {
-                        if id_value == format!("{:?}", bubble.id) {
-                            item["content"] = json!(trimmed);
-                            break;
-                        }
-                    }
+        if let Some(edit_id) = self.editing_bubble.take() {
+            match self.chat_bubbles.iter_mut().find(|b| b.id == edit_id) {
+                Some(bubble) => {
+                    bubble.content = trimmed.to_string();
+                    self.history_dirty = true;
+                    self.input_text.clear();
                 }
-                save_memory(&*history);
+                None => self.push_system_notice("The message being edited no longer exists.".to_owned(), true),
             }
-            self.input_text.clear();
             return;
         }
         let bubble_id = unique_id("bubble", trimmed);
-        self.chat_bubbles.push(ChatBubble {
-            sender: Sender::User,
-            content: trimmed.to_string(),
-            attachment_content: None,
-            is_thinking: false,
-            is_code: false,
-            language: None,
-            id: bubble_id,
-            timestamp: None,
-            persistent: true,
-        });
-        {
-            let mut history = self.conversation_history.lock().unwrap();
-            history.push(json!({
-                "role": "user",
-                "content": trimmed,
-                "id": format!("{:?}", bubble_id)
-            }));
-            save_memory(&*history);
+        // Attachments added since the last message belong to this one.
+        for attachment in std::mem::take(&mut self.pending_attachments) {
+            self.chat_bubbles.push(ChatBubble {
+                sender: Sender::User,
+                content: attachment.filename.clone(),
+                id: unique_id("attachment", &attachment.filename),
+                attachment: Some(attachment),
+                is_thinking: false,
+                is_code: false,
+                language: None,
+                timestamp: None,
+                persistent: true,
+                message_id: bubble_id,
+                truncated: false,
+                thinking_secs: None,
+            });
         }
-        self.input_text.clear();
-        self.scroll_to_bottom = true;
-        let (tx, rx) = unbounded_channel();
-        self.conversation_channels.push(rx);
-        let client = self.client.clone();
+        if !trimmed.is_empty() {
+            self.chat_bubbles.push(ChatBubble {
+                sender: Sender::User,
+                content: trimmed.to_string(),
+                attachment: None,
+                is_thinking: false,
+                is_code: false,
+                language: None,
+                id: bubble_id,
+                timestamp: None,
+                persistent: true,
+                message_id: bubble_id,
+                truncated: false,
+                thinking_secs: None,
+            });
+        }
+        self.rebuild_conversation_history();
+        self.input_text.clear();
+        self.request_reply();
+    }
+
+    // Asks the model to answer the conversation as it stands.
+    fn request_reply(&mut self) {
+        self.scroll_to_bottom = true;
+        let (tx, rx) = unbounded_channel();
+        let tx = BubbleSender { tx, ctx: self.ctx.clone() };
+        self.conversation_channels.push(rx);
+        let client = self.client.clone();
         let api_url = self.api_url.clone();
         let model = self.selected_model.clone();
         let history_arc = Arc::clone(&self.conversation_history);
@@ -1006,16 +1752,27 @@ impl ChatApp {
         let max_completion_tokens = self.max_completion_tokens;
         let experimental_reasoning = self.experimental_reasoning;
         let streaming_enabled = self.streaming_enabled;
+        let reasoning_tags = self.reasoning_tags.clone();
+        let messages = messages_for_model(
+            &self.conversation_history.lock().unwrap(),
+            self.reasoning_policy(),
+            &reasoning_tags,
+        );
+        let jit_load = self.jit_load;
+        let root = server_root(&self.api_url);
+        let load_options = self.load_options.get(&model).cloned().unwrap_or_default();
+        let model_events = self.model_events.clone();
+        self.last_model_use = Instant::now();
         tokio::spawn(async move {
+            if jit_load && !ensure_model_loaded(&client, &root, &model, &load_options, &tx, &model_events).await {
+                return;
+            }
             if streaming_enabled {
                 call_model_streaming(
                     &client,
                     &api_url,
                     &model,
-                    {
-                        let history = history_arc.lock().unwrap();
-                        history.clone()
-                    },
+                    messages,
                     experimental_reasoning,
                     temperature,
                     top_p,
@@ -1027,6 +1784,7 @@ impl ChatApp {
                     tts_enabled.clone(),
                     tts_stop_flag.clone(),
                     history_arc.clone(),
+                    reasoning_tags,
                 )
                 .await;
             } else {
@@ -1034,10 +1792,7 @@ impl ChatApp {
                     &client,
                     &api_url,
                     &model,
-                    {
-                        let history = history_arc.lock().unwrap();
-                        history.clone()
-                    },
+                    messages,
                     experimental_reasoning,
                     temperature,
                     top_p,
@@ -1047,39 +1802,315 @@ impl ChatApp {
                     max_completion_tokens,
                 )
                 .await;
-                if experimental_reasoning {
-                    let re = regex::Regex::new(r"(?s)<think> (.*?)</think>").unwrap();
-                    if let Some(captures) = re.captures(&model_response.content) {
-                        let extracted_reasoning = captures.get(1).unwrap().as_str().trim().to_owned();
-                        model_response.reasoning = Some(extracted_reasoning);
-                        model_response.content = re.replace(&model_response.content, "").trim().to_string();
-                    }
+                let split = split_reasoning(&model_response.content, &reasoning_tags);
+                if !split.reasoning.trim().is_empty() {
+                    model_response.reasoning = Some(match model_response.reasoning.take() {
+                        Some(existing) => format!("{}\n{}", existing, split.reasoning),
+                        None => split.reasoning,
+                    });
                 }
+                model_response.content = split.content;
                 model_response.content = model_response.content.trim().to_string();
                 if let Some(ref mut r) = model_response.reasoning {
                     *r = r.trim().to_string();
                 }
+                let message_id = unique_id("bubble", &model_response.content);
                 if experimental_reasoning {
                     if let Some(ref reasoning) = model_response.reasoning {
-                        send_reasoning(&tx, Sender::Model, reasoning);
+                        // Reasoning time is only measured while streaming.
+                        send_reasoning(&tx, Sender::Model, reasoning, message_id, None);
                     }
                 }
-                send_bubbles(&tx, Sender::Model, &model_response.content, false);
+                let truncated = model_response.finish_reason.as_deref() == Some("length");
+                send_bubbles(&tx, Sender::Model, &model_response.content, false, message_id, truncated);
                 {
                     let mut history = history_arc.lock().unwrap();
-                    let bubble_id = unique_id("bubble", &model_response.content);
                     history.push(json!({
                         "role": "assistant",
                         "content": model_response.content.clone(),
-                        "id": format!("{:?}", bubble_id)
+                        "id": format!("{:?}", message_id)
                     }));
-                    save_memory(&*history);
                 }
                 process_tts(&model_response.content, &tts_enabled, tts_stop_flag);
             }
         });
     }
 
+    fn start_model_task(&mut self, model: &str, action: ModelAction) {
+        if self.model_task.is_some() {
+            return;
+        }
+        self.model_task = Some(ModelTask { model: model.to_owned(), action, started: Instant::now(), fraction: None });
+        self.model_status = None;
+        let client = self.client.clone();
+        let root = server_root(&self.api_url);
+        let events = self.model_events.clone();
+        if action == ModelAction::Load {
+            let options = self.load_options.get(model).cloned().unwrap_or_default();
+            tokio::spawn(lmstudio::load_model(client, root, model.to_owned(), options, events));
+        } else {
+            tokio::spawn(lmstudio::unload_model(client, root, model.to_owned(), events));
+        }
+    }
+
+    fn push_system_notice(&mut self, content: String, expires: bool) {
+        self.chat_bubbles.push(notice_bubble(content, expires));
+    }
+
+    fn process_model_events(&mut self) {
+        while let Ok(event) = self.model_events_rx.try_recv() {
+            match event {
+                ModelEvent::Progress { model, fraction } => {
+                    if let Some(task) = self.model_task.as_mut().filter(|task| task.model == model) {
+                        task.fraction = Some(fraction);
+                    }
+                }
+                ModelEvent::Loaded { model, secs, warning } => {
+                    self.model_task = None;
+                    let mut status = format!("Model {} loaded in {:.1}s.", model, secs);
+                    if let Some(warning) = warning {
+                        status = format!("{} {}", status, warning);
+                    }
+                    self.push_system_notice(status.clone(), true);
+                    self.model_status = Some(status);
+                }
+                ModelEvent::Unloaded { model } => {
+                    self.model_task = None;
+                    let status = format!("Model {} unloaded.", model);
+                    self.push_system_notice(status.clone(), true);
+                    self.model_status = Some(status);
+                }
+                ModelEvent::Failed { model, action, error } => {
+                    self.model_task = None;
+                    let status = format!("Failed to {} model {}: {}", action.verb(), model, error);
+                    self.push_system_notice(status.clone(), true);
+                    self.model_status = Some(status);
+                }
+                ModelEvent::Owned(instances) => {
+                    self.owned_instances.extend(instances);
+                }
+                ModelEvent::Listed(loaded) => {
+                    // Instances unloaded elsewhere are no longer ours to unload.
+                    if let Ok(ref list) = loaded {
+                        self.owned_instances.retain(|id| list.iter().any(|loaded| &loaded.instance_id == id));
+                    }
+                    self.loaded_models = loaded;
+                }
+            }
+        }
+    }
+
+    fn unload_idle_models(&mut self, ctx: &egui::Context) {
+        if self.idle_unload_mins == 0 || self.owned_instances.is_empty() {
+            return;
+        }
+        let timeout = Duration::from_secs(self.idle_unload_mins as u64 * 60);
+        let idle = self.last_model_use.elapsed();
+        if idle < timeout || !self.conversation_channels.is_empty() || self.model_task.is_some() {
+            ctx.request_repaint_after(timeout.saturating_sub(idle).max(Duration::from_secs(1)));
+            return;
+        }
+        // A failed unload is retried only after another full timeout.
+        self.last_model_use = Instant::now();
+        self.model_task = Some(ModelTask {
+            model: "idle models".to_owned(),
+            action: ModelAction::Unload,
+            started: Instant::now(),
+            fraction: None,
+        });
+        let client = self.client.clone();
+        let root = server_root(&self.api_url);
+        let instances = self.owned_instances.iter().cloned().collect();
+        tokio::spawn(lmstudio::unload_instances(client, root, instances, self.model_events.clone()));
+    }
+
+    fn update_conversation(&mut self) {
+        match self.conversation {
+            ConversationState::Off => {}
+            ConversationState::Listening => {
+                let idle = !self.stt_recording && !self.stt_transcribing;
+                let started = if idle { self.start_stt_recording() } else { Ok(()) };
+                if let Err(e) = started {
+                    self.conversation = ConversationState::Off;
+                    self.push_system_notice(format!("Conversation stopped: {}", e), false);
+                }
+            }
+            ConversationState::Thinking => {
+                if self.conversation_channels.is_empty() {
+                    if tts_active() {
+                        // Keep the microphone running behind the speech so the user can barge in.
+                        self.conversation = ConversationState::Speaking;
+                        let started = if self.stt_recording { Ok(()) } else { self.start_stt_recording() };
+                        if let Err(e) = started {
+                            eprintln!("STT error (conversation): {:?}", e);
+                        }
+                    } else {
+                        self.conversation = ConversationState::Listening;
+                    }
+                }
+            }
+            ConversationState::Speaking => {
+                if self.barge_in.swap(false, Ordering::Relaxed) {
+                    self.tts_stop_flag.store(true, Ordering::Relaxed);
+                    self.conversation = ConversationState::Listening;
+                } else if !tts_active() {
+                    self.conversation = ConversationState::Listening;
+                }
+            }
+        }
+    }
+
+    fn reasoning_policy(&self) -> ReasoningHistory {
+        self.reasoning_history.get(&self.selected_model).copied().unwrap_or_default()
+    }
+
+    // Drops everything after the last user message and asks for a new answer to it.
+    fn regenerate_last_reply(&mut self) {
+        if !self.conversation_channels.is_empty() {
+            self.push_system_notice("A reply is still being generated.".to_owned(), true);
+            return;
+        }
+        let Some(last_user) = self.chat_bubbles.iter().rposition(|b| b.sender == Sender::User && b.persistent) else {
+            self.push_system_notice("There is no message to regenerate a reply for.".to_owned(), true);
+            return;
+        };
+        self.tts_stop_flag.store(true, Ordering::Relaxed);
+        let message_id = self.chat_bubbles[last_user].message_id;
+        let end = self.chat_bubbles.iter().rposition(|b| b.message_id == message_id).unwrap_or(last_user) + 1;
+        self.chat_bubbles.truncate(end);
+        // An edit of the dropped reply has nothing left to save into.
+        if self.editing_bubble.is_some_and(|id| !self.chat_bubbles.iter().any(|b| b.id == id)) {
+            self.editing_bubble = None;
+        }
+        self.rebuild_conversation_history();
+        self.request_reply();
+    }
+
+    fn read_clipboard(&mut self) -> Option<String> {
+        let text = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text());
+        match text {
+            Ok(text) if !text.trim().is_empty() => Some(text),
+            Ok(_) => {
+                self.push_system_notice("The clipboard has no text.".to_owned(), true);
+                None
+            }
+            Err(e) => {
+                self.push_system_notice(format!("Could not read the clipboard: {}", e), false);
+                None
+            }
+        }
+    }
+
+    fn speak_message(&self, message_id: egui::Id) {
+        let text = message_markdown(&self.chat_bubbles, message_id);
+        // Explicit replay speaks even when automatic TTS is switched off.
+        process_tts(&text, &Arc::new(AtomicBool::new(true)), self.tts_stop_flag.clone());
+    }
+
+    fn continue_message(&mut self, message_id: egui::Id) {
+        let partial = message_markdown(&self.chat_bubbles, message_id);
+        if partial.is_empty() {
+            return;
+        }
+        for bubble in self.chat_bubbles.iter_mut().filter(|b| b.message_id == message_id) {
+            bubble.truncated = false;
+        }
+        let history_id = format!("{:?}", message_id);
+        let mut messages: Vec<Value> = {
+            let history = self.conversation_history.lock().unwrap();
+            let earlier: Vec<Value> = history
+                .iter()
+                .take_while(|item| item.get("id").and_then(|v| v.as_str()) != Some(history_id.as_str()))
+                .cloned()
+                .collect();
+            messages_for_model(&earlier, self.reasoning_policy(), &self.reasoning_tags)
+        };
+        messages.push(json!({ "role": "assistant", "content": partial }));
+        messages.push(json!({
+            "role": "user",
+            "content": "Continue exactly where your previous reply stopped. Do not repeat anything you already wrote."
+        }));
+        let (tx, rx) = unbounded_channel();
+        let tx = BubbleSender { tx, ctx: self.ctx.clone() };
+        self.conversation_channels.push(rx);
+        self.last_model_use = Instant::now();
+        let client = self.client.clone();
+        let api_url = self.api_url.clone();
+        let model = self.selected_model.clone();
+        let tts_enabled = self.tts_enabled.clone();
+        let tts_stop_flag = self.tts_stop_flag.clone();
+        let temperature = self.temperature;
+        let top_p = self.top_p;
+        let min_p = self.min_p;
+        let top_k = self.top_k;
+        let repeat_penalty = self.repeat_penalty;
+        let max_completion_tokens = self.max_completion_tokens;
+        let experimental_reasoning = self.experimental_reasoning;
+        let streaming_enabled = self.streaming_enabled;
+        let reasoning_tags = self.reasoning_tags.clone();
+        let jit_load = self.jit_load;
+        let root = server_root(&self.api_url);
+        let load_options = self.load_options.get(&model).cloned().unwrap_or_default();
+        let model_events = self.model_events.clone();
+        tokio::spawn(async move {
+            if jit_load && !ensure_model_loaded(&client, &root, &model, &load_options, &tx, &model_events).await {
+                let _ = tx.send(BubbleMessage::Append { message_id, content: String::new(), truncated: true });
+                return;
+            }
+            let result = if streaming_enabled {
+                let payload = json!({
+                    "model": model,
+                    "messages": messages,
+                    "return_reasoning": experimental_reasoning,
+                    "temperature": temperature,
+                    "top_p": top_p,
+                    "min_p": min_p,
+                    "top_k": top_k,
+                    "repeat_penalty": repeat_penalty,
+                    "max_completion_tokens": max_completion_tokens,
+                    "stream": true,
+                });
+                stream_continuation(&client, &api_url, &payload, message_id, reasoning_tags, &tx).await
+            } else {
+                let model_response = call_model(
+                    &client,
+                    &api_url,
+                    &model,
+                    messages,
+                    experimental_reasoning,
+                    temperature,
+                    top_p,
+                    min_p,
+                    top_k,
+                    repeat_penalty,
+                    max_completion_tokens,
+                )
+                .await;
+                if model_response.failed {
+                    Err(model_response.content)
+                } else {
+                    let truncated = model_response.finish_reason.as_deref() == Some("length");
+                    let continuation = split_reasoning(&model_response.content, &reasoning_tags).content;
+                    let _ = tx.send(BubbleMessage::Append {
+                        message_id,
+                        content: continuation.clone(),
+                        truncated,
+                    });
+                    Ok(continuation)
+                }
+            };
+            match result {
+                Ok(continuation) => process_tts(&continuation, &tts_enabled, tts_stop_flag),
+                // An error is not part of the answer: report it and keep the Continue button for a retry.
+                Err(error) => {
+                    let notice = notice_bubble(format!("Could not continue the reply: {}", error), false);
+                    let _ = tx.send(BubbleMessage::New(notice));
+                    let _ = tx.send(BubbleMessage::Append { message_id, content: String::new(), truncated: true });
+                }
+            }
+        });
+    }
+
     fn update_top_panel(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
@@ -1091,6 +2122,28 @@ impl ChatApp {
                 }
                 if ui.button("Settings").clicked() {
                     self.show_settings = true;
+                    self.input_devices = input_device_names();
+                }
+                ui.separator();
+                if self.conversation == ConversationState::Off {
+                    if ui.button("Start Conversation").clicked() {
+                        self.conversation = ConversationState::Listening;
+                    }
+                } else {
+                    if ui.button("End Conversation").clicked() {
+                        self.conversation = ConversationState::Off;
+                        if self.stt_recording {
+                            self.stop_stt_recording();
+                        }
+                    }
+                    let state = match self.conversation {
+                        ConversationState::Listening if self.stt_transcribing => "Transcribing...",
+                        ConversationState::Listening => "Listening...",
+                        ConversationState::Thinking => "Thinking...",
+                        ConversationState::Speaking => "Speaking...",
+                        ConversationState::Off => "",
+                    };
+                    ui.label(state);
                 }
             });
         });
@@ -1110,6 +2163,28 @@ impl ChatApp {
                         .clamp(20.0, 300.0);
                 }
                 ui.add_space(4.0);
+                let mut removed = None;
+                let mut open_error = None;
+                for (index, attachment) in self.pending_attachments.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Attachment: {}", attachment.summary()));
+                        ui.weak("Sent with your next message");
+                        if ui.small_button("Open").clicked() {
+                            if let Err(e) = attachment.open() {
+                                open_error = Some(format!("Could not open {}: {}", attachment.filename, e));
+                            }
+                        }
+                        if ui.small_button("Remove").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = removed {
+                    self.pending_attachments.remove(index);
+                }
+                if let Some(error) = open_error {
+                    self.push_system_notice(error, false);
+                }
                 ui.horizontal(|ui| {
                     let spacing = ui.spacing().item_spacing.x;
                     let send_button_width = 60.0;
@@ -1144,17 +2219,122 @@ impl ChatApp {
                         } else {
                             if let Err(e) = self.start_stt_recording() {
                                 eprintln!("STT error: {:?}", e);
+                                self.push_system_notice(format!("Could not start recording: {}", e), false);
                             }
                         }
                     }
                     if ui.button("Upload").clicked() {
                         self.handle_file_upload();
                     }
+                    if ui.button("Attach Folder").clicked() {
+                        self.pick_folder();
+                    }
+                    if self.folder_scanning.is_some() && self.folder_dialog.is_none() {
+                        ui.spinner();
+                        ui.label("Scanning folder...");
+                    }
+                    if self.audio_uploads_pending > 0 {
+                        ui.spinner();
+                        ui.label(format!("Transcribing {} audio file(s)...", self.audio_uploads_pending));
+                    }
                 });
             });
         });
     }
 
+    fn update_folder_window(&mut self, ctx: &egui::Context) {
+        let Some(mut scan) = self.folder_dialog.take() else {
+            return;
+        };
+        let mut open = true;
+        let mut rescan = false;
+        let mut attach = false;
+        let mut cancelled = false;
+        let name = scan.root.file_name().unwrap_or_default().to_string_lossy().to_string();
+        egui::Window::new(format!("Attach folder {}", name))
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(scan.root.display().to_string());
+                egui::Grid::new("folder_options").num_columns(2).show(ui, |ui| {
+                    ui.label("Include (e.g. *.rs, src/**/*.toml):");
+                    rescan |= ui.text_edit_singleline(&mut self.folder_options.include).lost_focus();
+                    ui.end_row();
+                    ui.label("Exclude:");
+                    rescan |= ui.text_edit_singleline(&mut self.folder_options.exclude).lost_focus();
+                    ui.end_row();
+                    ui.label("Token budget:");
+                    if ui
+                        .add(egui::DragValue::new(&mut self.folder_options.token_budget).range(0..=1_000_000).speed(500))
+                        .changed()
+                    {
+                        scan.select_within(self.folder_options.token_budget);
+                    }
+                    ui.end_row();
+                });
+                ui.separator();
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    egui::Grid::new("folder_files").num_columns(2).striped(true).show(ui, |ui| {
+                        for file in &mut scan.files {
+                            match file.skipped {
+                                Some(ref reason) => {
+                                    ui.add_enabled(false, egui::Checkbox::new(&mut false, &file.relative));
+                                    ui.weak(format!("skipped: {}", reason));
+                                }
+                                None => {
+                                    ui.checkbox(&mut file.selected, &file.relative);
+                                    ui.label(format!("~{} tokens", file.tokens));
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+                if scan.truncated {
+                    ui.weak("Only the first files were scanned; narrow the include patterns to see the rest.");
+                }
+                ui.separator();
+                if self.folder_scanning.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Scanning...");
+                    });
+                }
+                let total = scan.selected_tokens();
+                let summary = format!(
+                    "{} files selected, ~{} of {} tokens",
+                    scan.selected_count(),
+                    total,
+                    self.folder_options.token_budget
+                );
+                if total > self.folder_options.token_budget {
+                    ui.colored_label(egui::Color32::RED, summary);
+                } else {
+                    ui.label(summary);
+                }
+                ui.horizontal(|ui| {
+                    let idle = self.folder_scanning.is_none();
+                    attach = ui.add_enabled(idle && scan.selected_count() > 0, egui::Button::new("Attach")).clicked();
+                    rescan |= ui.add_enabled(idle, egui::Button::new("Rescan")).clicked();
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            });
+        if attach {
+            self.save_settings();
+            match scan.assemble() {
+                Ok(blocks) => self.push_attachment(AttachmentKind::Folder, name, "text/markdown", blocks.into_bytes()),
+                Err(e) => self.push_system_notice(format!("Could not attach folder: {}", e), false),
+            }
+        } else if rescan && open && !cancelled {
+            self.scan_folder_in_background(scan.root.clone());
+            self.folder_dialog = Some(scan);
+        } else if open && !cancelled {
+            self.folder_dialog = Some(scan);
+        } else {
+            self.folder_scanning = None;
+        }
+    }
+
     fn update_settings_window(&mut self, ctx: &egui::Context) {
         if self.show_settings {
             let mut temp_api_url = self.temp_api_url.clone();
@@ -1167,8 +2347,24 @@ impl ChatApp {
             let mut tts_enabled_val = self.tts_enabled.load(Ordering::Relaxed);
             let mut streaming_enabled_val = self.streaming_enabled;
             let mut send_stt_val = self.send_stt;
+            let mut vad_enabled_val = self.vad_enabled;
+            let mut input_device = self.input_device.clone();
+            let mut keep_recordings_val = self.keep_recordings;
+            let mut whisper_model = self.whisper_model.clone();
+            let mut stt_language = self.stt_language.clone();
+            let mut stt_translate_val = self.stt_translate;
+            let mut stt_context_prompt_val = self.stt_context_prompt;
+            let mut live_transcription_val = self.live_transcription;
+            let mut image_options = self.image_options.clone();
+            let mut push_to_talk_val = self.push_to_talk;
+            let mut save_hotkeys = false;
+            let mut vad_silence_ms = self.vad_silence_ms;
+            let mut jit_load_val = self.jit_load;
+            let mut idle_unload_mins = self.idle_unload_mins;
             let mut selected_model = self.selected_model.clone();
             let mut selected_voice = self.selected_voice.clone();
+            let mut save_reasoning_tags = false;
+            let mut model_action: Option<(String, ModelAction)> = None;
             let mut changed = false;
             egui::Window::new("Settings")
                 .open(&mut self.show_settings)
@@ -1251,6 +2447,122 @@ impl ChatApp {
                     if ui.checkbox(&mut send_stt_val, "Send STT").changed() {
                         changed = true;
                     }
+                    ui.horizontal(|ui| {
+                        let selected = if input_device.is_empty() { "System default" } else { input_device.as_str() };
+                        egui::ComboBox::from_label("Input Device")
+                            .selected_text(selected.to_owned())
+                            .show_ui(ui, |ui| {
+                                if ui.selectable_value(&mut input_device, String::new(), "System default").changed() {
+                                    changed = true;
+                                }
+                                for name in &self.input_devices {
+                                    if ui.selectable_value(&mut input_device, name.clone(), name).changed() {
+                                        changed = true;
+                                    }
+                                }
+                            });
+                        if ui.small_button("Refresh").clicked() {
+                            self.input_devices = input_device_names();
+                        }
+                    });
+                    if ui.checkbox(&mut keep_recordings_val, "Keep recordings (saved to recordings/)").changed() {
+                        changed = true;
+                    }
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_label("Whisper Model")
+                            .selected_text(whisper_model.clone())
+                            .show_ui(ui, |ui| {
+                                for size in WHISPER_MODEL_SIZES {
+                                    if ui.selectable_value(&mut whisper_model, size.to_string(), *size).changed() {
+                                        changed = true;
+                                    }
+                                }
+                            });
+                        let language_name = STT_LANGUAGES
+                            .iter()
+                            .find(|(code, _)| *code == stt_language)
+                            .map_or(stt_language.clone(), |(_, name)| name.to_string());
+                        egui::ComboBox::from_label("Spoken Language")
+                            .selected_text(language_name)
+                            .show_ui(ui, |ui| {
+                                for (code, name) in STT_LANGUAGES {
+                                    if ui.selectable_value(&mut stt_language, code.to_string(), *name).changed() {
+                                        changed = true;
+                                    }
+                                }
+                            });
+                    });
+                    if ui.checkbox(&mut stt_translate_val, "Translate speech to English").changed() {
+                        changed = true;
+                    }
+                    if ui.checkbox(&mut stt_context_prompt_val, "Prime STT with recent conversation").changed() {
+                        changed = true;
+                    }
+                    if ui.checkbox(&mut live_transcription_val, "Show live transcript while recording").changed() {
+                        changed = true;
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("STT Hotkey:");
+                        ui.text_edit_singleline(&mut self.temp_stt_hotkey);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.checkbox(&mut push_to_talk_val, "Push-to-talk:").changed() {
+                            save_hotkeys = true;
+                            changed = true;
+                        }
+                        ui.text_edit_singleline(&mut self.temp_push_to_talk_hotkey);
+                    });
+                    egui::Grid::new("command_hotkeys").num_columns(2).show(ui, |ui| {
+                        for (label, hotkey, _) in self.temp_command_hotkeys.entries_mut() {
+                            ui.label(format!("{}:", label));
+                            ui.add(egui::TextEdit::singleline(hotkey).hint_text("unbound"));
+                            ui.end_row();
+                        }
+                    });
+                    if ui.button("Save Hotkeys").clicked() {
+                        save_hotkeys = true;
+                        changed = true;
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.checkbox(&mut vad_enabled_val, "Stop STT after silence (ms):").changed() {
+                            changed = true;
+                        }
+                        let silence = egui::DragValue::new(&mut vad_silence_ms).range(300..=10_000).speed(50);
+                        if ui.add_enabled(vad_enabled_val, silence).changed() {
+                            changed = true;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Image uploads: max edge (px, 0 = original):");
+                        if ui.add(egui::DragValue::new(&mut image_options.max_edge).range(0..=8192).speed(16)).changed() {
+                            changed = true;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Image format:");
+                        egui::ComboBox::from_id_salt("image_format")
+                            .selected_text(image_options.format.label())
+                            .show_ui(ui, |ui| {
+                                for format in ImageOutput::ALL {
+                                    if ui.selectable_value(&mut image_options.format, format, format.label()).changed() {
+                                        changed = true;
+                                    }
+                                }
+                            });
+                        if image_options.format == ImageOutput::Jpeg
+                            && ui.add(egui::Slider::new(&mut image_options.jpeg_quality, 1..=100).text("Quality")).changed()
+                        {
+                            changed = true;
+                        }
+                    });
+                    ui.label("Reasoning Tags (open,close; ...):");
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.temp_reasoning_tags);
+                        if ui.button("Save Tags").clicked() {
+                            save_reasoning_tags = true;
+                            changed = true;
+                        }
+                    });
                     ui.separator();
                     ui.label("TTS Voice:");
                     egui::ComboBox::from_label("Voice")
@@ -1275,35 +2587,88 @@ impl ChatApp {
                                     }
                                 }
                             });
-                        if ui.button("Load Model").clicked() {
-                            load_model(&selected_model);
-                            self.chat_bubbles.push(ChatBubble {
-                                sender: Sender::System,
-                                content: format!("Model {} loaded.", selected_model),
-                                attachment_content: None,
-                                is_thinking: false,
-                                is_code: false,
-                                language: None,
-                                id: unique_id("bubble", &selected_model),
-                                timestamp: Some(Instant::now()),
-                                persistent: true,
-                            });
+                        let busy = self.model_task.is_some();
+                        if ui.add_enabled(!busy, egui::Button::new("Load Model")).clicked() {
+                            let options = self.load_options.get(&selected_model).cloned().unwrap_or_default();
+                            self.load_dialog = Some((selected_model.clone(), options));
                         }
-                        if ui.button("Unload Model").clicked() {
-                            unload_model(&selected_model);
-                            self.chat_bubbles.push(ChatBubble {
-                                sender: Sender::System,
-                                content: format!("Model {} unloaded.", selected_model),
-                                attachment_content: None,
-                                is_thinking: false,
-                                is_code: false,
-                                language: None,
-                                id: unique_id("bubble", &selected_model),
-                                timestamp: Some(Instant::now()),
-                                persistent: true,
-                            });
+                        if ui.add_enabled(!busy, egui::Button::new("Unload Model")).clicked() {
+                            model_action = Some((selected_model.clone(), ModelAction::Unload));
                         }
                     });
+                    if let Some(task) = &self.model_task {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            let verb = match task.action {
+                                ModelAction::Load => "Loading",
+                                ModelAction::Unload => "Unloading",
+                            };
+                            match task.fraction {
+                                Some(fraction) => {
+                                    ui.label(format!("{} {}...", verb, task.model));
+                                    ui.add(egui::ProgressBar::new(fraction).show_percentage());
+                                }
+                                None => {
+                                    ui.label(format!("{} {}... {:.0}s", verb, task.model, task.started.elapsed().as_secs_f32()));
+                                }
+                            }
+                        });
+                    } else if let Some(status) = &self.model_status {
+                        ui.label(status);
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Loaded Models:");
+                        if ui.small_button("Refresh").clicked() {
+                            let client = self.client.clone();
+                            let root = server_root(&self.api_url);
+                            tokio::spawn(lmstudio::refresh_loaded(client, root, self.model_events.clone()));
+                        }
+                    });
+                    match &self.loaded_models {
+                        Ok(loaded) if loaded.is_empty() => {
+                            ui.label("None");
+                        }
+                        Ok(loaded) => {
+                            for model in loaded {
+                                ui.horizontal(|ui| {
+                                    let memory = model.size_bytes.map(format_bytes).unwrap_or_else(|| "? GB".to_owned());
+                                    match model.context_length {
+                                        Some(context) => ui.label(format!("{} ({}, {} ctx)", model.instance_id, memory, context)),
+                                        None => ui.label(format!("{} ({})", model.instance_id, memory)),
+                                    };
+                                    if ui.add_enabled(self.model_task.is_none(), egui::Button::new("Unload").small()).clicked() {
+                                        model_action = Some((model.instance_id.clone(), ModelAction::Unload));
+                                    }
+                                });
+                            }
+                        }
+                        Err(error) => {
+                            ui.label(error);
+                        }
+                    }
+                    if ui.checkbox(&mut jit_load_val, "Load model on send").changed() {
+                        changed = true;
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Unload idle models after (min, 0 = never):");
+                        if ui.add(egui::DragValue::new(&mut idle_unload_mins).range(0..=1440)).changed() {
+                            changed = true;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Past reasoning:");
+                        let mut policy = self.reasoning_history.get(&selected_model).copied().unwrap_or_default();
+                        egui::ComboBox::from_id_salt("reasoning_history")
+                            .selected_text(policy.label())
+                            .show_ui(ui, |ui| {
+                                for option in ReasoningHistory::ALL {
+                                    if ui.selectable_value(&mut policy, option, option.label()).changed() {
+                                        self.reasoning_history.insert(selected_model.clone(), policy);
+                                        changed = true;
+                                    }
+                                }
+                            });
+                    });
                     ui.horizontal(|ui| {
                         ui.label("New Model:");
                         ui.text_edit_singleline(&mut self.new_model_name);
@@ -1328,6 +2693,53 @@ impl ChatApp {
                         }
                     });
                 });
+            if let Some((model, options)) = self.load_dialog.take() {
+                let mut open = true;
+                let mut confirmed = false;
+                let mut cancelled = false;
+                let mut options = options;
+                egui::Window::new(format!("Load {}", model))
+                    .open(&mut open)
+                    .collapsible(false)
+                    .show(ctx, |ui| {
+                        egui::Grid::new("load_options").num_columns(2).show(ui, |ui| {
+                            ui.label("Context length (0 = default):");
+                            ui.add(egui::DragValue::new(&mut options.context_length).range(0..=1_048_576).speed(256));
+                            ui.end_row();
+                            ui.label("GPU offload:");
+                            ui.horizontal(|ui| {
+                                let mut custom_gpu = options.gpu_offload.is_some();
+                                if ui.checkbox(&mut custom_gpu, "Set").changed() {
+                                    options.gpu_offload = custom_gpu.then_some(1.0);
+                                }
+                                if let Some(ratio) = options.gpu_offload.as_mut() {
+                                    ui.add(egui::Slider::new(ratio, 0.0..=1.0).text("ratio"));
+                                }
+                            });
+                            ui.end_row();
+                            ui.label("Idle TTL seconds (0 = none):");
+                            ui.add(egui::DragValue::new(&mut options.ttl_secs).range(0..=604_800).speed(60));
+                            ui.end_row();
+                            ui.label("Identifier:");
+                            ui.text_edit_singleline(&mut options.identifier);
+                            ui.end_row();
+                        });
+                        ui.horizontal(|ui| {
+                            confirmed = ui.button("Load").clicked();
+                            cancelled = ui.button("Cancel").clicked();
+                        });
+                    });
+                if confirmed {
+                    self.load_options.insert(model.clone(), options);
+                    self.save_settings();
+                    model_action = Some((model, ModelAction::Load));
+                } else if open && !cancelled {
+                    self.load_dialog = Some((model, options));
+                }
+            }
+            if let Some((model, action)) = model_action {
+                self.start_model_task(&model, action);
+            }
             if changed {
                 self.temp_api_url = temp_api_url;
                 self.temperature = temperature;
@@ -1339,7 +2751,31 @@ impl ChatApp {
                 self.tts_enabled.store(tts_enabled_val, Ordering::Relaxed);
                 self.streaming_enabled = streaming_enabled_val;
                 self.send_stt = send_stt_val;
+                self.vad_enabled = vad_enabled_val;
+                self.input_device = input_device;
+                self.keep_recordings = keep_recordings_val;
+                self.whisper_model = whisper_model;
+                self.stt_language = stt_language;
+                self.stt_translate = stt_translate_val;
+                self.stt_context_prompt = stt_context_prompt_val;
+                self.live_transcription = live_transcription_val;
+                self.image_options = image_options;
+                if save_hotkeys {
+                    self.push_to_talk = push_to_talk_val;
+                    self.stt_hotkey = self.temp_stt_hotkey.trim().to_owned();
+                    self.push_to_talk_hotkey = self.temp_push_to_talk_hotkey.trim().to_owned();
+                    self.command_hotkeys = self.temp_command_hotkeys.clone();
+                    self.restart_hotkeys();
+                }
+                self.vad_silence_ms = vad_silence_ms;
+                self.jit_load = jit_load_val;
+                self.idle_unload_mins = idle_unload_mins;
                 self.selected_model = selected_model;
+                if save_reasoning_tags {
+                    let tags = parse_reasoning_tags(&self.temp_reasoning_tags);
+                    self.reasoning_tags = if tags.is_empty() { default_reasoning_tags() } else { tags };
+                    self.temp_reasoning_tags = format_reasoning_tags(&self.reasoning_tags);
+                }
                 if self.selected_voice != selected_voice {
                     self.selected_voice = selected_voice.clone();
                     *SELECTED_VOICE_PATH.lock().unwrap() = selected_voice.clone();
@@ -1347,17 +2783,7 @@ impl ChatApp {
                 }
                 self.api_url = self.temp_api_url.clone();
                 self.save_settings();
-                self.chat_bubbles.push(ChatBubble {
-                    sender: Sender::System,
-                    content: "Settings updated.".to_owned(),
-                    attachment_content: None,
-                    is_thinking: false,
-                    is_code: false,
-                    language: None,
-                    id: unique_id("bubble", "Settings updated"),
-                    timestamp: Some(Instant::now()),
-                    persistent: true,
-                });
+                self.push_system_notice("Settings updated.".to_owned(), true);
             }
         }
     }
@@ -1365,11 +2791,33 @@ impl ChatApp {
     fn update_chat_area(&mut self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
             let available_width = ui.available_width();
-            egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::ScrollArea::vertical().show_viewport(ui, |ui, viewport| {
                 ui.set_width(available_width);
-                for (i, bubble) in self.chat_bubbles.clone().into_iter().enumerate() {
-                    render_chat_bubble(ui, &bubble, i, self);
+                // Only bubbles intersecting the viewport are laid out; the rest are stood in for by
+                // their last measured height so long chats don't re-render every bubble per frame.
+                let origin = ui.cursor().top();
+                let height_of = |app: &ChatApp, i: usize| {
+                    app.bubble_heights
+                        .get(&app.chat_bubbles[i].id)
+                        .copied()
+                        .unwrap_or(ESTIMATED_BUBBLE_HEIGHT)
+                };
+                let mut index = 0;
+                let mut skipped = 0.0;
+                while index < self.chat_bubbles.len() && skipped + height_of(self, index) < viewport.min.y {
+                    skipped += height_of(self, index);
+                    index += 1;
+                }
+                ui.add_space(skipped);
+                while index < self.chat_bubbles.len() && ui.cursor().top() - origin <= viewport.max.y {
+                    let bubble = self.chat_bubbles[index].clone();
+                    let top = ui.cursor().top();
+                    render_chat_bubble(ui, &bubble, index, self);
+                    self.bubble_heights.insert(bubble.id, ui.cursor().top() - top);
+                    index += 1;
                 }
+                let remaining: f32 = (index..self.chat_bubbles.len()).map(|i| height_of(self, i)).sum();
+                ui.add_space(remaining);
                 if self.scroll_to_bottom {
                     ui.allocate_space(egui::vec2(0.0, 0.0));
                     ui.scroll_to_cursor(Some(egui::Align::BOTTOM));
@@ -1385,6 +2833,7 @@ impl ChatApp {
                 match message {
                     BubbleMessage::New(bubble) => {
                         self.chat_bubbles.push(bubble);
+                        self.history_dirty = true;
                         self.scroll_to_bottom = true;
                     }
                     BubbleMessage::Update { id, content } => {
@@ -1395,42 +2844,181 @@ impl ChatApp {
                     }
                     BubbleMessage::Remove(id) => {
                         self.chat_bubbles.retain(|b| b.id != id);
+                        self.history_dirty = true;
+                        self.scroll_to_bottom = true;
+                    }
+                    BubbleMessage::Append { message_id, content, truncated } => {
+                        let Some(pos) = self
+                            .chat_bubbles
+                            .iter()
+                            .position(|b| b.message_id == message_id && !b.is_thinking)
+                        else {
+                            continue;
+                        };
+                        let combined = format!("{}{}", message_markdown(&self.chat_bubbles, message_id), content);
+                        self.chat_bubbles.retain(|b| b.message_id != message_id || b.is_thinking);
+                        let mut bubbles = split_content_into_bubbles(Sender::Model, &combined, false, message_id);
+                        for bubble in bubbles.iter_mut() {
+                            bubble.truncated = truncated;
+                        }
+                        self.chat_bubbles.splice(pos..pos, bubbles);
+                        self.history_dirty = true;
                         self.scroll_to_bottom = true;
                     }
                 }
             }
-            !channel.is_closed()
+            // The idle timeout counts from when the model last finished answering.
+            let open = !channel.is_closed();
+            if !open {
+                self.last_model_use = Instant::now();
+                // Conversation mode runs earlier in the frame and has to see the reply is done.
+                self.ctx.request_repaint();
+            }
+            open
         });
     }
 
-    fn update_app(&mut self, ctx: &egui::Context) {
-        // Handle hotkey commands first
-        while let Ok(command) = self.hotkey_rx.try_recv() {
-            match command {
-                HotkeyCommand::ToggleSTT => {
+    fn handle_hotkey_command(&mut self, command: HotkeyCommand) {
+        match command {
+            HotkeyCommand::ToggleSTT => {
+                if self.stt_recording {
+                    self.stop_stt_recording_and_transcribe_heavy();
+                } else {
+                    if let Err(e) = self.start_stt_recording() {
+                        eprintln!("STT error (from hotkey): {:?}", e);
+                        self.push_system_notice(format!("Could not start recording: {}", e), false);
+                    }
+                }
+            }
+            HotkeyCommand::PushToTalkStart => {
+                if !self.stt_recording {
+                    match self.start_stt_recording() {
+                        Ok(()) => self.ptt_recording = true,
+                        Err(e) => {
+                            eprintln!("STT error (from push-to-talk): {:?}", e);
+                            self.push_system_notice(format!("Could not start recording: {}", e), false);
+                        }
+                    }
+                }
+            }
+            HotkeyCommand::PushToTalkStop => {
+                if self.ptt_recording {
+                    self.ptt_recording = false;
                     if self.stt_recording {
                         self.stop_stt_recording_and_transcribe_heavy();
-                    } else {
-                        if let Err(e) = self.start_stt_recording() {
-                            eprintln!("STT error (from hotkey): {:?}", e);
-                        }
                     }
                 }
             }
+            HotkeyCommand::StopSpeech => {
+                self.tts_stop_flag.store(true, Ordering::Relaxed);
+            }
+            HotkeyCommand::ToggleTTS => {
+                let enabled = !self.tts_enabled.load(Ordering::Relaxed);
+                self.tts_enabled.store(enabled, Ordering::Relaxed);
+                if !enabled {
+                    self.tts_stop_flag.store(true, Ordering::Relaxed);
+                }
+                self.save_settings();
+                let state = if enabled { "on" } else { "off" };
+                self.push_system_notice(format!("Text-to-speech {}", state), true);
+            }
+            HotkeyCommand::SpeakClipboard => {
+                if let Some(text) = self.read_clipboard() {
+                    // Read aloud as is, even when automatic TTS is switched off.
+                    process_tts(&text, &Arc::new(AtomicBool::new(true)), self.tts_stop_flag.clone());
+                }
+            }
+            HotkeyCommand::AskClipboard => {
+                if let Some(text) = self.read_clipboard() {
+                    // Sent as its own message; a draft or edit in progress is left alone.
+                    let draft = std::mem::replace(&mut self.input_text, text);
+                    let editing = self.editing_bubble.take();
+                    self.process_input();
+                    self.input_text = draft;
+                    self.editing_bubble = editing;
+                }
+            }
+            HotkeyCommand::Regenerate => self.regenerate_last_reply(),
+        }
+    }
+
+    fn update_app(&mut self, ctx: &egui::Context) {
+        // Handle hotkey commands first
+        while let Ok(command) = self.hotkey_rx.try_recv() {
+            self.handle_hotkey_command(command);
+        }
+
+        while let Ok(message) = self.audio_error_rx.try_recv() {
+            if self.stt_recording {
+                self.stop_stt_recording();
+            }
+            self.push_system_notice(message, false);
+        }
+
+        // The detector heard speech followed by enough silence. Push-to-talk runs until the key is released.
+        if self.stt_recording && self.vad_stop.swap(false, Ordering::Relaxed) && !self.ptt_recording {
+            self.stop_stt_recording_and_transcribe_heavy();
+        }
+
+        self.update_conversation();
+
+        // Partial transcripts only show while recording; the final one replaces them.
+        while let Ok(partial) = self.partial_rx.try_recv() {
+            if self.stt_recording {
+                self.input_text = partial;
+            }
+        }
+
+        while let Ok((root, result)) = self.folder_scan_rx.try_recv() {
+            if self.folder_scanning.as_ref() != Some(&root) {
+                continue;
+            }
+            self.folder_scanning = None;
+            match result {
+                Ok(scan) => self.folder_dialog = Some(scan),
+                Err(e) => self.push_system_notice(format!("Could not read folder: {}", e), false),
+            }
+        }
+
+        while let Ok((filename, result)) = self.audio_upload_rx.try_recv() {
+            self.audio_uploads_pending = self.audio_uploads_pending.saturating_sub(1);
+            match result {
+                Ok(transcript) => self.push_attachment(AttachmentKind::Transcript, filename, "text/plain", transcript.into_bytes()),
+                Err(e) => self.push_system_notice(format!("Could not transcribe {}: {}", filename, e), false),
+            }
         }
 
         // Process transcription results
-        while let Ok(new_text) = self.transcription_rx.try_recv() {
-            if self.send_stt && !new_text.trim().is_empty() {
+        while let Ok(result) = self.transcription_rx.try_recv() {
+            self.stt_transcribing = false;
+            let conversing = self.conversation != ConversationState::Off;
+            let new_text = match result {
+                Ok(text) => text,
+                // Shown as a notice, never sent as the user's turn.
+                Err(e) => {
+                    if conversing {
+                        self.conversation = ConversationState::Off;
+                        self.push_system_notice(format!("Conversation stopped: transcription failed: {}", e), false);
+                    } else {
+                        self.push_system_notice(format!("Transcription failed: {}", e), false);
+                    }
+                    continue;
+                }
+            };
+            if (self.send_stt || conversing) && !new_text.trim().is_empty() {
                 self.input_text = new_text;
                 self.process_input();
                 self.input_text.clear();
+                if conversing {
+                    self.conversation = ConversationState::Thinking;
+                }
             } else {
                 self.input_text = new_text;
             }
         }
 
         // Clean up system messages
+        let bubble_count = self.chat_bubbles.len();
         self.chat_bubbles.retain(|bubble| {
             if bubble.sender == Sender::System {
                 bubble.timestamp.map(|ts| ts.elapsed() < Duration::from_secs(1)).unwrap_or(true)
@@ -1438,35 +3026,40 @@ impl ChatApp {
                 true
             }
         });
+        if self.chat_bubbles.len() != bubble_count {
+            self.history_dirty = true;
+        }
 
         // Update UI components
         self.update_top_panel(ctx);
         self.update_input_panel(ctx);
         self.update_settings_window(ctx);
+        self.update_folder_window(ctx);
         self.update_chat_area(ctx);
         self.process_conversation_channels();
-        self.rebuild_conversation_history();
-
-        // Force repaints even when the app is in background
-        if self.background_repaint_timer.elapsed() > Duration::from_millis(100) {
-            ctx.request_repaint();
-            self.background_repaint_timer = Instant::now();
+        self.process_model_events();
+        self.unload_idle_models(ctx);
+        if self.history_dirty {
+            self.rebuild_conversation_history();
         }
+        self.flush_memory_if_due();
 
-        // Additional force repaint counter for background operation
-        self.force_repaint_counter = self.force_repaint_counter.wrapping_add(1);
-        if self.force_repaint_counter % 5 == 0 {
+        // Background producers wake the UI themselves; only schedule what this frame left pending.
+        if self.scroll_to_bottom {
             ctx.request_repaint();
         }
-
-        // Normal repaint conditions
-        if self.last_repaint.elapsed() > Duration::from_millis(16)
-            || self.scroll_to_bottom
-            || !self.conversation_channels.is_empty()
-            || !self.input_text.is_empty()
+        if let Some(expiry) = self
+            .chat_bubbles
+            .iter()
+            .filter(|b| b.sender == Sender::System)
+            .filter_map(|b| b.timestamp)
+            .map(|ts| Duration::from_secs(1).saturating_sub(ts.elapsed()))
+            .min()
         {
-            ctx.request_repaint();
-            self.last_repaint = Instant::now();
+            ctx.request_repaint_after(expiry);
+        }
+        if let Some(due) = self.memory_save_due {
+            ctx.request_repaint_after(due.saturating_duration_since(Instant::now()));
         }
     }
 }
@@ -1505,20 +3098,59 @@ fn render_chat_bubble(ui: &mut egui::Ui, bubble: &ChatBubble, index: usize, app:
                 }
             });
         } else if bubble.is_thinking {
-            render_collapsible_bubble(ui, "Reasoning:", bubble.id, |ui| {
+            let label = match bubble.thinking_secs {
+                Some(secs) => format!("Reasoning (thought for {:.1}s):", secs),
+                None => "Reasoning:".to_owned(),
+            };
+            render_collapsible_bubble(ui, &label, bubble.id, |ui| {
                 render_markdown(ui, &bubble.content);
             });
+        } else if let Some(ref attachment) = bubble.attachment {
+            ui.label(egui::RichText::new(format!("Attachment: {}", attachment.summary())).color(egui::Color32::WHITE));
         } else {
             ui.label(egui::RichText::new(&bubble.content).color(egui::Color32::WHITE));
         }
         ui.horizontal(|ui| {
-            if ui.add_sized([40.0, 20.0], egui::Button::new("Edit")).clicked() {
-                app.input_text = bubble.content.clone();
-                app.editing_bubble = Some(index);
+            match bubble.attachment {
+                Some(ref attachment) => {
+                    if ui.add_sized([50.0, 20.0], egui::Button::new("Open")).clicked() {
+                        if let Err(e) = attachment.open() {
+                            app.push_system_notice(format!("Could not open {}: {}", attachment.filename, e), false);
+                        }
+                    }
+                }
+                None => {
+                    if ui.add_sized([40.0, 20.0], egui::Button::new("Edit")).clicked() {
+                        app.input_text = bubble.content.clone();
+                        app.editing_bubble = Some(bubble.id);
+                    }
+                }
             }
             if ui.add_sized([50.0, 20.0], egui::Button::new("Delete")).clicked() {
                 app.chat_bubbles.remove(index);
-                app.rebuild_conversation_history();
+                app.history_dirty = true;
+            }
+            if bubble.sender == Sender::Model && !bubble.is_thinking {
+                if ui.add_sized([50.0, 20.0], egui::Button::new("Speak")).clicked() {
+                    app.speak_message(bubble.message_id);
+                }
+                if ui.add_sized([40.0, 20.0], egui::Button::new("Copy")).clicked() {
+                    let markdown = message_markdown(&app.chat_bubbles, bubble.message_id);
+                    ui.output_mut(|o| {
+                        o.copied_text = markdown;
+                    });
+                }
+                let is_last_of_message = app
+                    .chat_bubbles
+                    .iter()
+                    .rposition(|b| b.message_id == bubble.message_id)
+                    == Some(index);
+                if bubble.truncated
+                    && is_last_of_message
+                    && ui.add_sized([70.0, 20.0], egui::Button::new("Continue")).clicked()
+                {
+                    app.continue_message(bubble.message_id);
+                }
             }
         });
     });
@@ -1526,9 +3158,6 @@ fn render_chat_bubble(ui: &mut egui::Ui, bubble: &ChatBubble, index: usize, app:
 impl eframe::App for ChatApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.update_app(ctx);
-
-        // CRITICAL: Force repaints even when app is in background
-        ctx.request_repaint_after(Duration::from_millis(100));
     }
 
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
@@ -1544,8 +3173,8 @@ impl eframe::App for ChatApp {
         }
 
         // Properly shut down the hotkey manager
-        if let Some(handle) = self.hotkey_interrupt_handle.take() {
-            handle.interrupt();
+        if let Some(handle) = self.hotkey_handle.take() {
+            handle.stop();
 
             // Give the hotkey manager time to shut down
             std::thread::sleep(Duration::from_millis(100));
@@ -1555,7 +3184,19 @@ impl eframe::App for ChatApp {
         self.save_settings();
 
         // Save memory one last time
-        save_memory(&*self.conversation_history.lock().unwrap());
+        if self.history_dirty {
+            self.rebuild_conversation_history();
+        }
+        self.memory_save_due = None;
+        let history = self.conversation_history.lock().unwrap().clone();
+        save_memory_and_wait(history.clone());
+        let referenced = history
+            .iter()
+            .filter_map(|entry| entry["attachments"].as_array())
+            .flatten()
+            .filter_map(|attachment| attachment["blob"].as_str().map(str::to_owned))
+            .collect();
+        prune_blobs(&referenced);
     }
 }
 #[tokio::main(flavor = "multi_thread")]
@@ -1569,6 +3210,51 @@ async fn main() {
             .with_min_inner_size(egui::vec2(320.0, 240.0)),
         ..Default::default()
     };
-    let chat_app = ChatApp::new();
-    let _ = eframe::run_native("AI Chat", native_options, Box::new(|_cc| Ok(Box::new(chat_app))));
+    let _ = eframe::run_native(
+        "AI Chat",
+        native_options,
+        Box::new(|cc| Ok(Box::new(ChatApp::new(cc.egui_ctx.clone())))),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reasoning::{ReasoningHistory, default_reasoning_tags, messages_for_model};
+
+    fn prompt() -> Value {
+        json!({ "role": "system", "content": "You are an AI companion.", "id": SYSTEM_PROMPT_ID })
+    }
+
+    #[test]
+    fn notices_never_reach_the_model() {
+        let mut bubbles = bubbles_from_history(&[prompt(), json!({ "role": "user", "content": "hi", "id": "u1" })]);
+        bubbles.push(notice_bubble("Could not start recording: no input device".to_owned(), false));
+        bubbles.push(notice_bubble("Settings updated.".to_owned(), true));
+        let history = history_from_bubbles(&bubbles);
+        let messages = messages_for_model(&history, ReasoningHistory::StripAll, &default_reasoning_tags());
+        assert_eq!(
+            messages,
+            [
+                json!({ "role": "system", "content": "You are an AI companion." }),
+                json!({ "role": "user", "content": "hi" }),
+            ]
+        );
+    }
+
+    #[test]
+    fn system_prompt_survives_a_restart() {
+        // A notice stored by an older version expires; the prompt keeps its id and never does.
+        let stored_notice = json!({ "role": "system", "content": "Model x loaded.", "id": "n1" });
+        let bubbles = bubbles_from_history(&[prompt(), stored_notice]);
+        assert!(bubbles[0].timestamp.is_none());
+        assert!(bubbles[1].timestamp.is_some());
+        assert_eq!(history_from_bubbles(&bubbles[..1]), [prompt()]);
+
+        // Files rewritten before the id was kept still start with the prompt.
+        let hashed = json!({ "role": "system", "content": "You are an AI companion.", "id": "0x1234" });
+        let bubbles = bubbles_from_history(&[hashed]);
+        assert!(bubbles[0].timestamp.is_none());
+        assert_eq!(history_from_bubbles(&bubbles), [prompt()]);
+    }
 }
@@ -0,0 +1,210 @@
+// Builds message content in the shape OpenAI-compatible /v1/chat/completions servers expect:
+// a plain string for text-only messages, otherwise "text" and "image_url" parts. Text files
+// travel inside labelled fenced blocks so the model can tell where each one starts and ends.
+use serde_json::{json, Value};
+use crate::attachments::{Attachment, AttachmentKind};
+
+pub fn message_content(text: &str, images: &[&str]) -> Value {
+    if images.is_empty() {
+        return json!(text);
+    }
+    let mut parts = vec![json!({ "type": "text", "text": text })];
+    parts.extend(images.iter().map(|url| json!({ "type": "image_url", "image_url": { "url": url } })));
+    json!(parts)
+}
+
+// Content sent for one history entry. Stored entries keep only the typed text plus attachment
+// metadata; here text attachments are expanded into fenced blocks ahead of the text and images
+// into parts. Older history files stored Responses-API parts ("input_text"/"input_image"),
+// which are rewritten to the chat-completions names.
+pub fn model_content(entry: &Value) -> Value {
+    let (mut text, mut images): (String, Vec<String>) = match entry["content"].as_array() {
+        Some(parts) => (
+            parts.iter().filter_map(|p| p["text"].as_str()).collect::<Vec<_>>().join("\n\n"),
+            parts
+                .iter()
+                .filter_map(|p| p["image_url"]["url"].as_str().or_else(|| p["image_url"].as_str()))
+                .map(str::to_owned)
+                .collect(),
+        ),
+        None => (entry["content"].as_str().unwrap_or_default().to_owned(), Vec::new()),
+    };
+    let attachments: Vec<Attachment> = match entry.get("attachments") {
+        Some(list) => serde_json::from_value(list.clone()).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    if attachments.is_empty() && images.is_empty() {
+        return entry["content"].clone();
+    }
+    let mut blocks = Vec::new();
+    for attachment in &attachments {
+        let result = match attachment.kind {
+            AttachmentKind::Image => attachment.data_url().map(|url| images.push(url)),
+            _ => attachment_block(attachment).map(|block| blocks.push(block)),
+        };
+        if let Err(e) = result {
+            blocks.push(format!("[Attachment {}: {}]", attachment.filename, e));
+        }
+    }
+    if !blocks.is_empty() {
+        blocks.push(text);
+        text = blocks.join("\n\n").trim().to_owned();
+    }
+    let images: Vec<&str> = images.iter().map(String::as_str).collect();
+    message_content(&text, &images)
+}
+
+fn attachment_block(attachment: &Attachment) -> Result<String, String> {
+    let text = attachment.text_content()?;
+    Ok(match attachment.kind {
+        // Already one labelled block per file.
+        AttachmentKind::Folder => text,
+        AttachmentKind::Text => fenced_file(&attachment.filename, language_for(&attachment.filename), &text),
+        AttachmentKind::Transcript => fenced_file(&format!("{} (transcript)", attachment.filename), "text", &text),
+        AttachmentKind::Document | AttachmentKind::Image => fenced_file(&attachment.filename, "text", &text),
+    })
+}
+
+// "File: src/main.rs" followed by the contents fenced with the file's language. The fence is
+// made longer than any backtick run inside, so code containing ``` cannot close it early.
+pub fn fenced_file(label: &str, language: &str, text: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let fence = "`".repeat((longest + 1).max(3));
+    format!("File: {}\n{}{}\n{}\n{}", label, fence, language, text.trim_end(), fence)
+}
+
+pub fn language_for(filename: &str) -> &'static str {
+    let extension = filename.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "rs" => "rust",
+        "toml" => "toml",
+        "py" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" => "typescript",
+        "tsx" => "tsx",
+        "jsx" => "jsx",
+        "json" => "json",
+        "md" | "markdown" => "markdown",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "cxx" | "hpp" => "cpp",
+        "cs" => "csharp",
+        "go" => "go",
+        "java" => "java",
+        "kt" => "kotlin",
+        "rb" => "ruby",
+        "php" => "php",
+        "swift" => "swift",
+        "sh" | "bash" => "bash",
+        "ps1" => "powershell",
+        "yaml" | "yml" => "yaml",
+        "xml" => "xml",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "sql" => "sql",
+        "lua" => "lua",
+        _ => "text",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The smallest valid PNG header is enough; nothing decodes it.
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    fn entry(text: &str, attachments: &[Attachment]) -> Value {
+        json!({ "role": "user", "content": text, "id": "1", "attachments": attachments })
+    }
+
+    #[test]
+    fn text_only_content_is_a_string() {
+        assert_eq!(message_content("hi", &[]), json!("hi"));
+        assert_eq!(model_content(&json!({ "role": "user", "content": "hi" })), json!("hi"));
+    }
+
+    #[test]
+    fn images_become_image_url_parts() {
+        assert_eq!(
+            message_content("what is this?", &["data:image/png;base64,AAAA"]),
+            json!([
+                { "type": "text", "text": "what is this?" },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,AAAA" } }
+            ])
+        );
+    }
+
+    #[test]
+    fn image_attachment_is_sent_as_a_part() {
+        let image = Attachment::new(AttachmentKind::Image, "shot.png".to_owned(), "image/png", PNG.to_vec()).unwrap();
+        assert!(image.content.is_none() && image.blob.is_some());
+        assert_eq!(
+            model_content(&entry("look", &[image])),
+            json!([
+                { "type": "text", "text": "look" },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw0KGgo=" } }
+            ])
+        );
+    }
+
+    #[test]
+    fn text_attachment_is_a_labelled_fence_before_the_text() {
+        let file = Attachment::text(AttachmentKind::Text, "main.rs".to_owned(), "fn main() {}\n".to_owned()).unwrap();
+        assert_eq!(
+            model_content(&entry("Review this.", &[file])),
+            json!("File: main.rs\n```rust\nfn main() {}\n```\n\nReview this.")
+        );
+    }
+
+    #[test]
+    fn transcript_and_image_together() {
+        let transcript =
+            Attachment::text(AttachmentKind::Transcript, "call.wav".to_owned(), "[00:00] hello".to_owned()).unwrap();
+        let image = Attachment::new(AttachmentKind::Image, "shot.png".to_owned(), "image/png", PNG.to_vec()).unwrap();
+        assert_eq!(
+            model_content(&entry("", &[transcript, image])),
+            json!([
+                { "type": "text", "text": "File: call.wav (transcript)\n```text\n[00:00] hello\n```" },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw0KGgo=" } }
+            ])
+        );
+    }
+
+    #[test]
+    fn legacy_parts_are_rewritten() {
+        let legacy = json!({
+            "role": "user",
+            "content": [
+                { "type": "input_text", "text": "old" },
+                { "type": "input_image", "image_url": "data:image/png;base64,BBBB" }
+            ]
+        });
+        assert_eq!(
+            model_content(&legacy),
+            json!([
+                { "type": "text", "text": "old" },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,BBBB" } }
+            ])
+        );
+    }
+
+    #[test]
+    fn fence_outgrows_backticks_in_the_file() {
+        assert_eq!(fenced_file("a.md", "markdown", "```rust\nx\n```\n"), "File: a.md\n````markdown\n```rust\nx\n```\n````");
+        assert_eq!(fenced_file("b.md", "markdown", "````\n"), "File: b.md\n`````markdown\n````\n`````");
+        assert_eq!(fenced_file("c.txt", "text", "one ` tick"), "File: c.txt\n```text\none ` tick\n```");
+    }
+
+    #[test]
+    fn languages_follow_the_extension() {
+        assert_eq!(language_for("src/main.rs"), "rust");
+        assert_eq!(language_for("App.TSX"), "tsx");
+        assert_eq!(language_for("Makefile"), "text");
+        assert_eq!(language_for("archive.tar.gz"), "text");
+    }
+}
@@ -0,0 +1,344 @@
+// Splits model output into visible content and reasoning wrapped in tag pairs like <think>...</think>.
+// Works incrementally so streamed deltas can cut a tag anywhere, e.g. "<thi" + "nk>".
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::messages::model_content;
+
+pub fn default_reasoning_tags() -> Vec<(String, String)> {
+    vec![
+        ("<think>".to_owned(), "</think>".to_owned()),
+        ("<thinking>".to_owned(), "</thinking>".to_owned()),
+    ]
+}
+
+// Parses "open,close; open,close" as edited in the settings window.
+pub fn parse_reasoning_tags(text: &str) -> Vec<(String, String)> {
+    text.split(';')
+        .filter_map(|pair| {
+            let (open, close) = pair.split_once(',')?;
+            let (open, close) = (open.trim(), close.trim());
+            if open.is_empty() || close.is_empty() {
+                None
+            } else {
+                Some((open.to_owned(), close.to_owned()))
+            }
+        })
+        .collect()
+}
+
+pub fn format_reasoning_tags(tags: &[(String, String)]) -> String {
+    tags.iter()
+        .map(|(open, close)| format!("{},{}", open, close))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[derive(Default)]
+pub struct SplitOutput {
+    pub content: String,
+    pub reasoning: String,
+}
+
+pub struct ReasoningSplitter {
+    tags: Vec<(String, String)>,
+    pending: String,
+    inside: Option<usize>,
+}
+
+impl ReasoningSplitter {
+    pub fn new(tags: Vec<(String, String)>) -> Self {
+        Self { tags, pending: String::new(), inside: None }
+    }
+
+    pub fn push(&mut self, chunk: &str) -> SplitOutput {
+        let mut out = SplitOutput::default();
+        let mut buffer = std::mem::take(&mut self.pending);
+        buffer.push_str(chunk);
+        loop {
+            match self.inside {
+                Some(tag_index) => {
+                    let close = &self.tags[tag_index].1;
+                    if let Some(pos) = buffer.find(close.as_str()) {
+                        out.reasoning.push_str(&buffer[..pos]);
+                        buffer.drain(..pos + close.len());
+                        self.inside = None;
+                    } else {
+                        let keep = partial_suffix_len(&buffer, std::iter::once(close.as_str()));
+                        out.reasoning.push_str(&buffer[..buffer.len() - keep]);
+                        self.pending = buffer[buffer.len() - keep..].to_owned();
+                        break;
+                    }
+                }
+                None => {
+                    let open = earliest_tag(&buffer, self.tags.iter().map(|(open, _)| open.as_str()));
+                    // A closing tag with no opener (the chat template may have sent the opener) is dropped.
+                    let stray_close = earliest_tag(&buffer, self.tags.iter().map(|(_, close)| close.as_str()));
+                    match (open, stray_close) {
+                        (Some((pos, tag_index, len)), close) if close.is_none_or(|(c, _, _)| pos <= c) => {
+                            out.content.push_str(&buffer[..pos]);
+                            buffer.drain(..pos + len);
+                            self.inside = Some(tag_index);
+                        }
+                        (_, Some((pos, _, len))) => {
+                            out.content.push_str(&buffer[..pos]);
+                            buffer.drain(..pos + len);
+                        }
+                        _ => {
+                            let tags = self.tags.iter().flat_map(|(open, close)| [open.as_str(), close.as_str()]);
+                            let keep = partial_suffix_len(&buffer, tags);
+                            out.content.push_str(&buffer[..buffer.len() - keep]);
+                            self.pending = buffer[buffer.len() - keep..].to_owned();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    // Flushes anything held back as a possible partial tag. An unclosed block stays reasoning.
+    pub fn finish(&mut self) -> SplitOutput {
+        let mut out = SplitOutput::default();
+        let pending = std::mem::take(&mut self.pending);
+        if self.inside.is_some() {
+            out.reasoning = pending;
+        } else {
+            out.content = pending;
+        }
+        self.inside = None;
+        out
+    }
+}
+
+pub fn split_reasoning(text: &str, tags: &[(String, String)]) -> SplitOutput {
+    let mut splitter = ReasoningSplitter::new(tags.to_vec());
+    // With the whole reply at hand, a leading close tag means the template already sent the opener.
+    let first_open = earliest_tag(text, tags.iter().map(|(open, _)| open.as_str()));
+    let first_close = earliest_tag(text, tags.iter().map(|(_, close)| close.as_str()));
+    let leading_close = first_close.filter(|(close_pos, _, _)| first_open.is_none_or(|(open_pos, _, _)| *close_pos < open_pos));
+    if let Some((_, tag_index, _)) = leading_close {
+        splitter.inside = Some(tag_index);
+    }
+    let mut out = splitter.push(text);
+    let rest = splitter.finish();
+    out.content.push_str(&rest.content);
+    out.reasoning.push_str(&rest.reasoning);
+    out
+}
+
+// Returns (position, tag index, tag length) of the first tag found, preferring the longest at a tie.
+fn earliest_tag<'a>(buffer: &str, tags: impl Iterator<Item = &'a str>) -> Option<(usize, usize, usize)> {
+    tags.enumerate()
+        .filter(|(_, tag)| !tag.is_empty())
+        .filter_map(|(index, tag)| buffer.find(tag).map(|pos| (pos, index, tag.len())))
+        .min_by(|a, b| a.0.cmp(&b.0).then(b.2.cmp(&a.2)))
+}
+
+// Length of the longest buffer suffix that could still grow into one of the tags.
+fn partial_suffix_len<'a>(buffer: &str, tags: impl Iterator<Item = &'a str>) -> usize {
+    let mut keep = 0;
+    for tag in tags {
+        for len in (keep + 1..tag.len()).rev() {
+            if buffer.is_char_boundary(buffer.len().saturating_sub(len))
+                && buffer.len() >= len
+                && tag.is_char_boundary(len)
+                && buffer.ends_with(&tag[..len])
+            {
+                keep = len;
+                break;
+            }
+        }
+    }
+    keep
+}
+
+// How much earlier reasoning is sent back to the model, chosen per model in settings.
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ReasoningHistory {
+    #[default]
+    StripAll,
+    KeepLast,
+    KeepAll,
+}
+
+impl ReasoningHistory {
+    pub const ALL: [ReasoningHistory; 3] = [Self::StripAll, Self::KeepLast, Self::KeepAll];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::StripAll => "Strip all",
+            Self::KeepLast => "Keep last turn",
+            Self::KeepAll => "Keep all",
+        }
+    }
+}
+
+// Builds the message list sent to the model from stored history. Reasoning lives in its own
+// "reasoning" field (older entries may still carry it inline as tags) and is only re-inlined
+// where the policy allows; bookkeeping fields like "id" are dropped.
+pub fn messages_for_model(history: &[Value], policy: ReasoningHistory, tags: &[(String, String)]) -> Vec<Value> {
+    let last_assistant = history.iter().rposition(|entry| entry["role"] == "assistant");
+    history
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let mut content = model_content(entry);
+            if let Some(text) = content.as_str().filter(|_| entry["role"] == "assistant") {
+                let split = split_reasoning(text, tags);
+                let reasoning = entry["reasoning"].as_str().unwrap_or(&split.reasoning).trim();
+                let keep = match policy {
+                    ReasoningHistory::StripAll => false,
+                    ReasoningHistory::KeepLast => Some(index) == last_assistant,
+                    ReasoningHistory::KeepAll => true,
+                };
+                content = match tags.first() {
+                    Some((open, close)) if keep && !reasoning.is_empty() => {
+                        json!(format!("{}{}{}\n\n{}", open, reasoning, close, split.content.trim()))
+                    }
+                    _ => json!(split.content.trim()),
+                };
+            }
+            json!({ "role": entry["role"], "content": content })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feeds the chunks one by one and returns everything the splitter produced, finish included.
+    fn split_chunks(tags: Vec<(String, String)>, chunks: &[&str]) -> (String, String) {
+        let mut splitter = ReasoningSplitter::new(tags);
+        let (mut content, mut reasoning) = (String::new(), String::new());
+        for chunk in chunks {
+            let out = splitter.push(chunk);
+            content.push_str(&out.content);
+            reasoning.push_str(&out.reasoning);
+        }
+        let out = splitter.finish();
+        content.push_str(&out.content);
+        reasoning.push_str(&out.reasoning);
+        (content, reasoning)
+    }
+
+    #[test]
+    fn holds_back_partial_tags() {
+        let tags = ["<think>", "</think>"];
+        assert_eq!(partial_suffix_len("Hello <thi", tags.into_iter()), 4);
+        assert_eq!(partial_suffix_len("Hello <", tags.into_iter()), 1);
+        assert_eq!(partial_suffix_len("idea</thin", tags.into_iter()), 6);
+        assert_eq!(partial_suffix_len("Hello", tags.into_iter()), 0);
+        // A complete tag is found by the caller, not held back.
+        assert_eq!(partial_suffix_len("<think>", tags.into_iter()), 0);
+
+        let mut splitter = ReasoningSplitter::new(default_reasoning_tags());
+        let out = splitter.push("Hi <thi");
+        assert_eq!(out.content, "Hi ");
+        assert_eq!(out.reasoning, "");
+    }
+
+    #[test]
+    fn open_tag_split_across_chunks() {
+        let (content, reasoning) = split_chunks(default_reasoning_tags(), &["Sure. <th", "ink>plan", " it</think>Done."]);
+        assert_eq!(content, "Sure. Done.");
+        assert_eq!(reasoning, "plan it");
+    }
+
+    #[test]
+    fn close_tag_split_across_chunks() {
+        let (content, reasoning) = split_chunks(default_reasoning_tags(), &["<think>a", "b</", "thi", "nk>answer"]);
+        assert_eq!(content, "answer");
+        assert_eq!(reasoning, "ab");
+    }
+
+    #[test]
+    fn every_split_point_gives_the_same_result() {
+        let text = "x<thinking>why</thinking>y";
+        for cut in 1..text.len() {
+            let (content, reasoning) = split_chunks(default_reasoning_tags(), &[&text[..cut], &text[cut..]]);
+            assert_eq!((content.as_str(), reasoning.as_str()), ("xy", "why"), "cut at {}", cut);
+        }
+    }
+
+    #[test]
+    fn custom_tag_pairs() {
+        let tags = parse_reasoning_tags("[[r]], [[/r]]; <reason>,</reason>");
+        assert_eq!(format_reasoning_tags(&tags), "[[r]],[[/r]]; <reason>,</reason>");
+        let (content, reasoning) = split_chunks(tags.clone(), &["A[[", "r]]one[[/r", "]]B<reas", "on>two</reason>C"]);
+        assert_eq!(content, "ABC");
+        assert_eq!(reasoning, "onetwo");
+        // The default tags mean nothing once replaced.
+        let (content, reasoning) = split_chunks(tags, &["<think>kept</think>"]);
+        assert_eq!(content, "<think>kept</think>");
+        assert_eq!(reasoning, "");
+    }
+
+    #[test]
+    fn unclosed_block_stays_reasoning() {
+        let (content, reasoning) = split_chunks(default_reasoning_tags(), &["Hi <think>still going</th"]);
+        assert_eq!(content, "Hi ");
+        assert_eq!(reasoning, "still going</th");
+    }
+
+    #[test]
+    fn partial_tag_at_finish_is_content() {
+        let (content, reasoning) = split_chunks(default_reasoning_tags(), &["a < b and <thi"]);
+        assert_eq!(content, "a < b and <thi");
+        assert_eq!(reasoning, "");
+    }
+
+    #[test]
+    fn leading_close_tag_means_the_opener_was_in_the_template() {
+        let out = split_reasoning("hidden</think>shown", &default_reasoning_tags());
+        assert_eq!(out.content, "shown");
+        assert_eq!(out.reasoning, "hidden");
+    }
+
+    fn history() -> Vec<Value> {
+        vec![
+            json!({ "role": "user", "content": "one", "id": "1" }),
+            json!({ "role": "assistant", "content": "first", "reasoning": "r1", "id": "2" }),
+            json!({ "role": "user", "content": "two", "id": "3" }),
+            // Older entries kept reasoning inline.
+            json!({ "role": "assistant", "content": "<think>r2</think>second", "id": "4" }),
+        ]
+    }
+
+    fn contents(policy: ReasoningHistory) -> Vec<Value> {
+        messages_for_model(&history(), policy, &default_reasoning_tags())
+            .into_iter()
+            .map(|message| message["content"].clone())
+            .collect()
+    }
+
+    #[test]
+    fn strip_all_removes_every_reasoning_block() {
+        assert_eq!(contents(ReasoningHistory::StripAll), [json!("one"), json!("first"), json!("two"), json!("second")]);
+    }
+
+    #[test]
+    fn keep_last_inlines_only_the_latest_reply() {
+        assert_eq!(
+            contents(ReasoningHistory::KeepLast),
+            [json!("one"), json!("first"), json!("two"), json!("<think>r2</think>\n\nsecond")]
+        );
+    }
+
+    #[test]
+    fn keep_all_inlines_every_reply() {
+        assert_eq!(
+            contents(ReasoningHistory::KeepAll),
+            [json!("one"), json!("<think>r1</think>\n\nfirst"), json!("two"), json!("<think>r2</think>\n\nsecond")]
+        );
+    }
+
+    #[test]
+    fn bookkeeping_fields_are_dropped() {
+        for message in messages_for_model(&history(), ReasoningHistory::KeepAll, &default_reasoning_tags()) {
+            let keys: Vec<&String> = message.as_object().unwrap().keys().collect();
+            assert_eq!(keys, ["content", "role"]);
+        }
+    }
+}
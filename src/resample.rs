@@ -0,0 +1,192 @@
+// Microphone input conditioning for Whisper: downmix to mono and band-limited resampling.
+// The resampler is a windowed-sinc interpolator that keeps its history between callbacks,
+// so audio can be fed in whatever chunk sizes the device delivers. The kernel is tabulated
+// once, so the per-sample work in the audio callback is multiply-adds only.
+use std::f64::consts::PI;
+
+// Zero crossings of the sinc on each side of a tap; more is sharper and slower.
+const ZERO_CROSSINGS: f64 = 16.0;
+// Passband edge as a fraction of the lower Nyquist frequency.
+const CUTOFF: f64 = 0.95;
+// Kernel table entries per input sample; taps in between are interpolated linearly.
+const PHASES: usize = 256;
+
+pub fn downmix(data: &[f32], channels: usize) -> Vec<f32> {
+    if channels <= 1 {
+        return data.to_vec();
+    }
+    data.chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+pub struct Resampler {
+    step: f64,
+    half_width: f64,
+    // Right half of the symmetric kernel at t = i / PHASES, with a trailing zero.
+    table: Vec<f32>,
+    buffer: Vec<f32>,
+    position: f64,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        let step = input_rate as f64 / output_rate as f64;
+        // Normalised to the input rate: half of the lower of the two rates, minus a guard band.
+        let cutoff = CUTOFF * 0.5 * (output_rate.min(input_rate) as f64 / input_rate as f64);
+        let half_width = (ZERO_CROSSINGS / (2.0 * cutoff)).ceil();
+        let mut table: Vec<f32> = (0..=half_width as usize * PHASES)
+            .map(|i| kernel(i as f64 / PHASES as f64, cutoff, half_width) as f32)
+            .collect();
+        table.push(0.0);
+        Self {
+            step,
+            half_width,
+            table,
+            // Silence before the first sample lets the first output land on input sample 0.
+            buffer: vec![0.0; half_width as usize],
+            position: half_width,
+        }
+    }
+
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.step == 1.0 {
+            return input.to_vec();
+        }
+        self.buffer.extend_from_slice(input);
+        let mut output = Vec::with_capacity((input.len() as f64 / self.step) as usize + 1);
+        while self.position + self.half_width < self.buffer.len() as f64 {
+            output.push(self.sample_at(self.position));
+            self.position += self.step;
+        }
+        let consumed = (self.position - self.half_width).floor().max(0.0) as usize;
+        self.buffer.drain(..consumed.min(self.buffer.len()));
+        self.position -= consumed as f64;
+        output
+    }
+
+    // Pushes the tail still held back for the filter through, for input that has ended.
+    pub fn flush(&mut self) -> Vec<f32> {
+        if self.step == 1.0 {
+            return Vec::new();
+        }
+        // Outputs past the last real input sample would only be the filter ringing out. The
+        // small allowance keeps rounding in `position` from adding one at an exact boundary.
+        let remaining = ((self.buffer.len() as f64 - self.position) / self.step - 1e-6).ceil().max(0.0) as usize;
+        let padding = vec![0.0; self.half_width as usize + 1];
+        let mut output = self.process(&padding);
+        output.truncate(remaining);
+        output
+    }
+
+    fn sample_at(&self, position: f64) -> f32 {
+        let first = (position - self.half_width).ceil().max(0.0) as usize;
+        let last = ((position + self.half_width).floor() as usize).min(self.buffer.len() - 1);
+        let mut sum = 0.0;
+        for index in first..=last {
+            let phase = (position - index as f64).abs() * PHASES as f64;
+            let slot = phase as usize;
+            let frac = (phase - slot as f64) as f32;
+            let tap = self.table[slot] + (self.table[slot + 1] - self.table[slot]) * frac;
+            sum += self.buffer[index] * tap;
+        }
+        sum
+    }
+}
+
+// Low-pass sinc at the cutoff, shaped by a Blackman window spanning the half width.
+fn kernel(t: f64, cutoff: f64, half_width: f64) -> f64 {
+    let x = 2.0 * cutoff * t;
+    let sinc = if x.abs() < 1e-9 { 1.0 } else { (PI * x).sin() / (PI * x) };
+    let w = 0.5 + 0.5 * t / half_width;
+    let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+    2.0 * cutoff * sinc * window
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(rate: u32, hz: f64, secs: f64) -> Vec<f32> {
+        (0..(rate as f64 * secs) as usize)
+            .map(|i| (2.0 * PI * hz * i as f64 / rate as f64).sin() as f32)
+            .collect()
+    }
+
+    fn resample(input: &[f32], from: u32, to: u32) -> Vec<f32> {
+        let mut resampler = Resampler::new(from, to);
+        let mut output = resampler.process(input);
+        output.extend(resampler.flush());
+        output
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    // Skips the filter's ramp-up and ramp-down at both ends.
+    fn middle(samples: &[f32]) -> &[f32] {
+        &samples[samples.len() / 4..samples.len() * 3 / 4]
+    }
+
+    #[test]
+    fn output_length_follows_the_rate_ratio() {
+        for (from, to) in [(48_000, 16_000), (44_100, 16_000), (16_000, 48_000), (22_050, 16_000)] {
+            let input = vec![0.0; from as usize];
+            let output = resample(&input, from, to);
+            assert!(output.len().abs_diff(to as usize) <= 1, "{} -> {}: {} samples", from, to, output.len());
+        }
+    }
+
+    #[test]
+    fn same_rate_passes_through() {
+        let input = sine(16_000, 440.0, 0.1);
+        assert_eq!(resample(&input, 16_000, 16_000), input);
+    }
+
+    #[test]
+    fn chunk_sizes_do_not_change_the_output() {
+        let input = sine(44_100, 1000.0, 0.5);
+        let whole = resample(&input, 44_100, 16_000);
+        let mut resampler = Resampler::new(44_100, 16_000);
+        let mut chunked = Vec::new();
+        for chunk in input.chunks(441) {
+            chunked.extend(resampler.process(chunk));
+        }
+        chunked.extend(resampler.flush());
+        assert_eq!(chunked.len(), whole.len());
+        assert!(chunked.iter().zip(&whole).all(|(a, b)| (a - b).abs() < 1e-6));
+    }
+
+    #[test]
+    fn keeps_dc() {
+        let output = resample(&vec![0.5; 48_000], 48_000, 16_000);
+        assert!(middle(&output).iter().all(|s| (s - 0.5).abs() < 1e-3));
+    }
+
+    #[test]
+    fn keeps_tones_in_the_passband() {
+        for (from, hz) in [(48_000, 1000.0), (44_100, 3000.0)] {
+            let output = resample(&sine(from, hz, 1.0), from, 16_000);
+            // Output sample n sits at input time n / 16000.
+            let expected = sine(16_000, hz, 1.0);
+            let error: Vec<f32> = middle(&output).iter().zip(middle(&expected)).map(|(a, b)| a - b).collect();
+            assert!(rms(&error) < 0.01, "{} Hz from {}: error {}", hz, from, rms(&error));
+        }
+    }
+
+    #[test]
+    fn attenuates_aliases_above_8khz() {
+        for (from, hz) in [(48_000, 9000.0), (48_000, 12_000.0), (44_100, 15_000.0)] {
+            let output = resample(&sine(from, hz, 1.0), from, 16_000);
+            let db = 20.0 * (rms(middle(&output)) / std::f32::consts::FRAC_1_SQRT_2).log10();
+            assert!(db < -60.0, "{} Hz from {} only down {:.1} dB", hz, from, db);
+        }
+    }
+
+    #[test]
+    fn downmixes_stereo_to_mono() {
+        assert_eq!(downmix(&[1.0, 3.0, -1.0, 1.0, 0.5, 0.5], 2), [2.0, 0.0, 0.5]);
+        assert_eq!(downmix(&[0.1, 0.2], 1), [0.1, 0.2]);
+    }
+}
@@ -0,0 +1,199 @@
+// Whisper transcription with the model kept resident between recordings.
+use super::*;
+use rodio::Source;
+
+const WHISPER_MODELS_DIR: &str = "models/";
+// Whisper reads at most 224 prompt tokens; a few hundred characters stays well inside that.
+const INITIAL_PROMPT_CHARS: usize = 400;
+// Live transcription re-runs Whisper on the open end of the recording once this much new audio arrives.
+const LIVE_STEP_SAMPLES: usize = STT_SAMPLE_RATE as usize;
+// Once the open window is longer than this, all but its last segment are fixed and the next
+// window starts at that segment, so passes stay short and consecutive windows overlap.
+const LIVE_WINDOW_SAMPLES: usize = 12 * STT_SAMPLE_RATE as usize;
+
+pub const WHISPER_MODEL_SIZES: &[&str] = &["tiny", "base", "small", "medium", "large"];
+pub const STT_LANGUAGES: &[(&str, &str)] = &[
+    ("auto", "Auto-detect"),
+    ("en", "English"),
+    ("de", "German"),
+    ("es", "Spanish"),
+    ("fr", "French"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+    ("uk", "Ukrainian"),
+    ("zh", "Chinese"),
+];
+
+#[derive(Clone)]
+pub struct SttOptions {
+    pub model_size: String,
+    pub language: String,
+    pub translate: bool,
+    pub initial_prompt: String,
+}
+
+// The loaded model and the size it was loaded for; a different size replaces it.
+static WHISPER: Lazy<Mutex<Option<(String, WhisperContext)>>> = Lazy::new(|| Mutex::new(None));
+
+fn with_model<R>(model_size: &str, f: impl FnOnce(&WhisperContext) -> R) -> Result<R, String> {
+    let mut slot = WHISPER.lock().unwrap();
+    if slot.as_ref().is_none_or(|(size, _)| size != model_size) {
+        *slot = None;
+        eprintln!("[STT] Loading Whisper {} model...", model_size);
+        // The first use of a size downloads it; that needs a runtime even off the UI thread.
+        let model_handler = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle.block_on(ModelHandler::new(model_size, WHISPER_MODELS_DIR)),
+            Err(_) => tokio::runtime::Runtime::new()
+                .map_err(|e| e.to_string())?
+                .block_on(ModelHandler::new(model_size, WHISPER_MODELS_DIR)),
+        };
+        let context = WhisperContext::new_with_params(&model_handler.get_model_dir(), WhisperContextParameters::default())
+            .map_err(|e| format!("failed to load Whisper model: {}", e))?;
+        *slot = Some((model_size.to_owned(), context));
+    }
+    Ok(f(&slot.as_ref().unwrap().1))
+}
+
+// Loads the model in the background so the first transcription does not wait for it.
+pub fn preload_whisper(model_size: String) {
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = with_model(&model_size, |_| ()) {
+            eprintln!("[STT] {}", e);
+        }
+    });
+}
+
+// Transcribes a clip into segments, each with the sample offset it starts at.
+fn transcribe_segments(samples: &[i16], options: &SttOptions) -> Result<Vec<(usize, String)>, String> {
+    let audio: Vec<f32> = samples.iter().map(|&s| s as f32 / i16::MAX as f32).collect();
+    with_model(&options.model_size, |context| -> Result<Vec<(usize, String)>, String> {
+        let mut state = context.create_state().map_err(|e| e.to_string())?;
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_language(Some(options.language.as_str()));
+        params.set_translate(options.translate);
+        if !options.initial_prompt.is_empty() {
+            params.set_initial_prompt(&options.initial_prompt);
+        }
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        state.full(params, &audio).map_err(|e| e.to_string())?;
+        let mut segments = Vec::new();
+        for segment in 0..state.full_n_segments().map_err(|e| e.to_string())? {
+            // Segment times are in centiseconds.
+            let start = state.full_get_segment_t0(segment).map_err(|e| e.to_string())?.max(0) as usize;
+            let text = state.full_get_segment_text(segment).map_err(|e| e.to_string())?;
+            segments.push((start * STT_SAMPLE_RATE as usize / 100, text));
+        }
+        Ok(segments)
+    })?
+}
+
+pub fn heavy_transcribe(samples: &[i16], options: &SttOptions) -> Result<String, String> {
+    Ok(transcribe_segments(samples, options)?.into_iter().map(|(_, text)| text).collect())
+}
+
+// Transcript lines stamped with where each segment starts, e.g. "[01:02] text".
+pub fn transcribe_with_timestamps(samples: &[i16], options: &SttOptions) -> Result<String, String> {
+    let mut lines = Vec::new();
+    for (start, text) in transcribe_segments(samples, options)? {
+        let secs = start / STT_SAMPLE_RATE as usize;
+        let stamp = if secs >= 3600 {
+            format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+        } else {
+            format!("{:02}:{:02}", secs / 60, secs % 60)
+        };
+        lines.push(format!("[{}] {}", stamp, text.trim()));
+    }
+    Ok(lines.join("\n"))
+}
+
+// Decodes a WAV, FLAC or Ogg Vorbis file to mono 16 kHz samples for Whisper.
+pub fn decode_audio_file(path: &Path) -> Result<Vec<i16>, String> {
+    let file = fs::File::open(path).map_err(|e| e.to_string())?;
+    let decoder = rodio::Decoder::new(std::io::BufReader::new(file)).map_err(|e| format!("could not decode audio: {}", e))?;
+    let channels = decoder.channels() as usize;
+    let mut resampler = Resampler::new(decoder.sample_rate(), STT_SAMPLE_RATE);
+    let audio: Vec<f32> = decoder.map(|s| s as f32 / i16::MAX as f32).collect();
+    let mut resampled = resampler.process(&downmix(&audio, channels));
+    resampled.extend(resampler.flush());
+    Ok(resampled.into_iter().map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).collect())
+}
+
+// Runs while a recording is active, sending the transcript so far after every pass. The
+// final transcript still comes from the whole clip once the recording stops.
+pub fn live_transcribe(samples: Arc<Mutex<Vec<i16>>>, active: Arc<AtomicBool>, options: SttOptions, on_partial: impl Fn(String)) {
+    let mut committed = String::new();
+    let mut window_start = 0;
+    let mut transcribed_len = 0;
+    while active.load(Ordering::Relaxed) {
+        let window: Vec<i16> = {
+            let samples = samples.lock().unwrap();
+            if samples.len() < transcribed_len + LIVE_STEP_SAMPLES {
+                drop(samples);
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            transcribed_len = samples.len();
+            samples[window_start..].to_vec()
+        };
+        let mut pass_options = options.clone();
+        pass_options.initial_prompt = initial_prompt_from(&format!("{} {}", options.initial_prompt, committed));
+        let mut segments = match transcribe_segments(&window, &pass_options) {
+            Ok(segments) => segments,
+            Err(e) => {
+                eprintln!("[STT] Live transcription stopped: {}", e);
+                return;
+            }
+        };
+        if window.len() > LIVE_WINDOW_SAMPLES && segments.len() > 1 {
+            let last = segments.pop().unwrap();
+            committed.extend(segments.drain(..).map(|(_, text)| text));
+            window_start += last.0;
+            segments.push(last);
+        }
+        let open: String = segments.into_iter().map(|(_, text)| text).collect();
+        if active.load(Ordering::Relaxed) {
+            on_partial(format!("{}{}", committed, open).trim().to_owned());
+        }
+    }
+}
+
+// The tail of the recent conversation, cut at a word boundary, so Whisper spells names the way the chat does.
+pub fn initial_prompt_from(recent_text: &str) -> String {
+    let text = recent_text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.len() <= INITIAL_PROMPT_CHARS {
+        return text;
+    }
+    let mut start = text.len() - INITIAL_PROMPT_CHARS;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    match text[start..].find(' ') {
+        Some(space) => text[start + space + 1..].to_owned(),
+        None => text[start..].to_owned(),
+    }
+}
+
+// Saves a clip and its transcript side by side as recordings/<millis>.wav and .txt.
+pub fn save_recording(samples: &[i16], transcript: &str) -> Result<(), String> {
+    fs::create_dir_all(RECORDINGS_DIR).map_err(|e| e.to_string())?;
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+    let base = Path::new(RECORDINGS_DIR).join(stamp.to_string());
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: STT_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(base.with_extension("wav"), spec).map_err(|e| e.to_string())?;
+    for &sample in samples {
+        writer.write_sample(sample).map_err(|e| e.to_string())?;
+    }
+    writer.finalize().map_err(|e| e.to_string())?;
+    fs::write(base.with_extension("txt"), transcript).map_err(|e| e.to_string())
+}
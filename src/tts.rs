@@ -3,7 +3,7 @@ use crate::heteronyms::HETERONYMS;
 use crate::contractions::word2ipa;
 use lazy_static::lazy_static;
 use futures::executor::block_on;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::mpsc::sync_channel;
 use std::time::Duration;
@@ -54,6 +54,38 @@ pub static ARPA_IPA_MAP: Lazy<std::collections::HashMap<&'static str, &'static s
     m
 });
 
+// Number of process_tts runs still synthesizing or playing.
+static TTS_ACTIVE: AtomicUsize = AtomicUsize::new(0);
+// Level of the audio currently leaving the speakers, as f32 dBFS bits, for echo-aware barge-in.
+static PLAYBACK_DB: AtomicU32 = AtomicU32::new(SILENT_DB.to_bits());
+const SILENT_DB: f32 = -100.0;
+
+// Called whenever a process_tts run finishes, so the UI can react without polling.
+type TtsCallback = Box<dyn Fn() + Send>;
+static TTS_FINISHED: Lazy<Mutex<Option<TtsCallback>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn tts_active() -> bool {
+    TTS_ACTIVE.load(Ordering::Relaxed) > 0
+}
+
+pub fn on_tts_finished(callback: impl Fn() + Send + 'static) {
+    *TTS_FINISHED.lock().unwrap() = Some(Box::new(callback));
+}
+
+pub fn playback_level_db() -> f32 {
+    f32::from_bits(PLAYBACK_DB.load(Ordering::Relaxed))
+}
+
+fn store_playback_level(window: &[f32]) {
+    let db = if window.is_empty() {
+        SILENT_DB
+    } else {
+        let energy = window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32;
+        (10.0 * (energy + 1e-10).log10()).max(SILENT_DB)
+    };
+    PLAYBACK_DB.store(db.to_bits(), Ordering::Relaxed);
+}
+
 enum AudioWorkerMessage {
     Play {
         samples: Vec<f32>,
@@ -109,13 +141,19 @@ fn audio_worker_thread(receiver: Receiver<AudioWorkerMessage>) {
                     }
                 };
                 
+                let levels = samples.clone();
                 let buffer = SamplesBuffer::new(1, 24000, samples);
                 sink.append(buffer);
                 sink.play();
+                let started = Instant::now();
                 
                 while !sink.empty() && !stop_flag.load(Ordering::Relaxed) {
+                    // 20 ms of what is playing now.
+                    let position = ((started.elapsed().as_secs_f32() * 24000.0) as usize).min(levels.len());
+                    store_playback_level(&levels[position..(position + 480).min(levels.len())]);
                     thread::sleep(Duration::from_millis(5));
                 }
+                store_playback_level(&[]);
                 
                 if stop_flag.load(Ordering::Relaxed) {
                     sink.stop();
@@ -1392,6 +1430,129 @@ pub async fn ensure_tts_model_loaded() {
     }
 }
 
+// Kokoro takes at most model_max_length input ids (512 in tokenizer_config.json, including the two
+// padding ids), and picks its style vector by sequence length, so a chunk must also stay within the
+// loaded voice pack.
+const MAX_INPUT_IDS: usize = 512;
+// Where an over-long sentence is cut, strongest first; the cut goes after the punctuation.
+const CLAUSE_BREAKS: &[&[&str]] = &[&["; ", ": ", " — ", "—", " – "], &[", ", ") "]];
+// Cut before these so the conjunction starts the next chunk.
+const CONJUNCTIONS: &[&str] = &[
+    " and ", " but ", " or ", " so ", " because ", " which ", " while ", " although ", " though ", " whereas ", " then ",
+];
+
+fn input_id_limit() -> usize {
+    let pack = loader::VOICE_PACK.lock().unwrap().len();
+    if pack == 0 { MAX_INPUT_IDS } else { MAX_INPUT_IDS.min(pack) }
+}
+
+fn input_id_count(text: &str) -> usize {
+    tokenizer::get_token_ids(&g2p(text)).len()
+}
+
+// Sentences, each cut further at clause boundaries when its phonemes would not fit in one model
+// call, so arbitrarily long replies are spoken completely.
+pub fn chunk_for_synthesis(text: &str, limit: usize) -> Vec<String> {
+    chunk_with(text, limit, &input_id_count)
+}
+
+// The chunking itself, with the id counter passed in so it can be swapped out.
+fn chunk_with(text: &str, limit: usize, count: &impl Fn(&str) -> usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    for sentence in split_sentences(text) {
+        let mut rest = sentence.as_str();
+        while !rest.is_empty() {
+            if count(rest) <= limit {
+                chunks.push(rest.to_owned());
+                break;
+            }
+            let cut = clause_cut(rest, limit, count);
+            chunks.push(rest[..cut].trim().to_owned());
+            rest = rest[cut..].trim_start();
+        }
+    }
+    chunks
+}
+
+// Keeps the end punctuation, which Kokoro uses for intonation. "3.5" or "e.g.this" do not end a
+// sentence; line breaks always do, so list items are spoken one by one.
+fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let end = match c {
+            '\n' => Some(index),
+            '.' | '!' | '?' | '…' => {
+                let mut end = index + c.len_utf8();
+                while let Some(&(next_index, next)) = chars.peek() {
+                    if !matches!(next, '.' | '!' | '?' | '"' | '\'' | ')' | '”' | '’') {
+                        break;
+                    }
+                    end = next_index + next.len_utf8();
+                    chars.next();
+                }
+                match chars.peek() {
+                    Some(&(_, next)) if !next.is_whitespace() => None,
+                    _ => Some(end),
+                }
+            }
+            _ => None,
+        };
+        if let Some(end) = end {
+            sentences.push(text[start..end].trim().to_owned());
+            start = end;
+        }
+    }
+    sentences.push(text[start..].trim().to_owned());
+    sentences.retain(|sentence| sentence.chars().any(char::is_alphanumeric));
+    sentences
+}
+
+// Byte offset to cut a too-long sentence at: the strongest clause boundary inside the longest
+// prefix that fits, unless that would leave a very short first chunk, then the last word that fits.
+fn clause_cut(text: &str, limit: usize, count: &impl Fn(&str) -> usize) -> usize {
+    let word_ends: Vec<usize> = text.char_indices().filter(|(_, c)| c.is_whitespace()).map(|(i, _)| i).collect();
+    let fit = match longest_fitting(text, &word_ends, limit, count) {
+        Some(fit) => fit,
+        // A single word longer than the limit is cut mid-word.
+        None => {
+            let char_ends: Vec<usize> = text.char_indices().skip(1).map(|(i, _)| i).collect();
+            return longest_fitting(text, &char_ends, limit, count).unwrap_or_else(|| char_ends.first().copied().unwrap_or(text.len()));
+        }
+    };
+    let prefix = text[..fit].to_ascii_lowercase();
+    let min_cut = fit / 3;
+    for class in CLAUSE_BREAKS {
+        let best = class
+            .iter()
+            .filter_map(|pattern| prefix.rfind(pattern).map(|pos| pos + pattern.trim_end().len()))
+            .max();
+        if let Some(cut) = best.filter(|&cut| cut > min_cut) {
+            return cut;
+        }
+    }
+    let conjunction = CONJUNCTIONS.iter().filter_map(|pattern| prefix.rfind(pattern)).max();
+    match conjunction {
+        Some(cut) if cut > min_cut => cut,
+        _ => fit,
+    }
+}
+
+// Largest offset whose prefix fits, by binary search; the id count grows with the prefix.
+fn longest_fitting(text: &str, offsets: &[usize], limit: usize, count: &impl Fn(&str) -> usize) -> Option<usize> {
+    let (mut low, mut high) = (0, offsets.len());
+    while low < high {
+        let mid = (low + high) / 2;
+        if count(&text[..offsets[mid]]) <= limit {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low.checked_sub(1).map(|index| offsets[index])
+}
+
 pub fn process_tts(text: &str, enabled: &Arc<AtomicBool>, tts_stop_flag: Arc<AtomicBool>) {
     tts_stop_flag.store(false, Ordering::Relaxed);
     let text_for_tts = strip_code_blocks(text);
@@ -1399,21 +1560,18 @@ pub fn process_tts(text: &str, enabled: &Arc<AtomicBool>, tts_stop_flag: Arc<Ato
         let text_clone = text_for_tts.clone();
         let flag_clone = tts_stop_flag.clone();
         let flag_clone_synthesis = tts_stop_flag.clone();
+        TTS_ACTIVE.fetch_add(1, Ordering::Relaxed);
         tokio::spawn(async move {
             ensure_tts_model_loaded().await;
             let (tx, rx) = sync_channel::<(Vec<f32>, String)>(2);
             let synthesis_handle = std::thread::spawn(move || {
-                let sentences: Vec<&str> = text_clone.split(|c| c == '.' || c == '!' || c == '?').collect();
-                for sentence in sentences {
+                for chunk in chunk_for_synthesis(&text_clone, input_id_limit()) {
                     if flag_clone_synthesis.load(Ordering::Relaxed) {
                         break;
                     }
-                    let trimmed_sentence = sentence.trim();
-                    if !trimmed_sentence.is_empty() {
-                        let (wave, _duration) = block_on(synthesizer::synth(trimmed_sentence.to_string(), 1.0));
-                        if let Err(_send_err) = tx.send((wave, trimmed_sentence.to_string())) {
-                            break;
-                        }
+                    let (wave, _duration) = block_on(synthesizer::synth(chunk.clone(), 1.0));
+                    if let Err(_send_err) = tx.send((wave, chunk)) {
+                        break;
                     }
                 }
             });
@@ -1435,6 +1593,94 @@ pub fn process_tts(text: &str, enabled: &Arc<AtomicBool>, tts_stop_flag: Arc<Ato
                 }
             }
             let _ = synthesis_handle.join();
+            TTS_ACTIVE.fetch_sub(1, Ordering::Relaxed);
+            if let Some(callback) = TTS_FINISHED.lock().unwrap().as_ref() {
+                callback();
+            }
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One id per character stands in for the phonemizer and tokenizer.
+    fn chars(text: &str) -> usize {
+        text.chars().count()
+    }
+
+    fn chunks(text: &str, limit: usize) -> Vec<String> {
+        chunk_with(text, limit, &chars)
+    }
+
+    fn words(text: &str) -> Vec<&str> {
+        text.split_whitespace().collect()
+    }
+
+    #[test]
+    fn short_sentences_stay_whole() {
+        assert_eq!(chunks("Hello there. How are you? Fine!", 100), ["Hello there.", "How are you?", "Fine!"]);
+        assert_eq!(chunks("It costs 3.5 dollars, e.g.this one.", 100), ["It costs 3.5 dollars, e.g.this one."]);
+        assert_eq!(chunks("- first item\n- second item", 100), ["- first item", "- second item"]);
+    }
+
+    #[test]
+    fn semicolon_beats_comma_and_conjunction() {
+        let text = "We went to the market, bought apples; then we walked home and cooked dinner.";
+        let result = chunks(text, 60);
+        assert_eq!(result[0], "We went to the market, bought apples;");
+    }
+
+    #[test]
+    fn comma_beats_conjunction() {
+        let text = "We went to the market and bought apples, pears and plums before walking home slowly.";
+        let result = chunks(text, 60);
+        assert_eq!(result[0], "We went to the market and bought apples,");
+    }
+
+    #[test]
+    fn conjunction_starts_the_next_chunk() {
+        // The last conjunction in the part that fits is used.
+        let text = "We went to the market and bought some apples but we walked all the way home.";
+        let result = chunks(text, 60);
+        assert_eq!(result[0], "We went to the market and bought some apples");
+        assert!(result[1].starts_with("but we"));
+    }
+
+    #[test]
+    fn early_breaks_fall_back_to_the_last_word() {
+        // The only comma would leave a tiny first chunk, so the cut goes at the last word that fits.
+        let text = "Well, the quick brown fox jumps over the lazy dog again tomorrow morning";
+        let result = chunks(text, 40);
+        assert_eq!(result[0], "Well, the quick brown fox jumps over the");
+    }
+
+    #[test]
+    fn long_words_are_cut_mid_word() {
+        let word = "a".repeat(25);
+        assert_eq!(chunks(&word, 10), ["a".repeat(10), "a".repeat(10), "a".repeat(5)]);
+    }
+
+    #[test]
+    fn chunks_fit_and_keep_every_word() {
+        let text = "The first clause runs on and on; it keeps going, with commas here and there, \
+            and conjunctions too, because run-on sentences are what the model chokes on — \
+            and a dash for good measure: plus a colon. Then a short one. \
+            Finally an extraordinarily-long-hyphenated-compound-word-that-will-not-fit ends it.";
+        for limit in [12, 20, 33, 50, 80, 500] {
+            let result = chunks(text, limit);
+            for chunk in &result {
+                assert!(chars(chunk) <= limit, "{:?} exceeds {}", chunk, limit);
+                assert!(!chunk.is_empty());
+            }
+            let joined = result.join(" ");
+            if limit >= 80 {
+                assert_eq!(words(&joined), words(text), "limit {}", limit);
+            } else {
+                // Words longer than the limit are split, so compare the text without spaces.
+                assert_eq!(words(&joined).concat(), words(text).concat(), "limit {}", limit);
+            }
+        }
+    }
+}
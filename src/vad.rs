@@ -0,0 +1,330 @@
+// Energy/spectral voice-activity detection for STT recordings.
+// A frame counts as speech when it is well above the tracked noise floor and most of its
+// power sits in the speech band; recording stops after a run of silence following speech.
+use num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+
+const FRAME_MS: u32 = 30;
+const SPEECH_BAND_HZ: (f32, f32) = (150.0, 4000.0);
+const MIN_SPEECH_DB: f32 = -55.0;
+const MIN_BAND_RATIO: f32 = 0.5;
+// Consecutive speech frames needed before a recording counts as having speech (~90 ms).
+const SPEECH_ONSET_FRAMES: u32 = 3;
+// Audio kept around detected speech when trimming.
+const TRIM_PADDING_MS: u32 = 200;
+// Speech above the expected echo needed to interrupt playback.
+const BARGE_IN_MS: u32 = 300;
+const BARGE_IN_MARGIN_DB: f32 = 10.0;
+
+pub struct VoiceActivityDetector {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    frame_len: usize,
+    sample_rate: u32,
+    threshold_db: f32,
+    silence_ms: u32,
+    pending: Vec<f32>,
+    noise_db: Option<f32>,
+    speech_run: u32,
+    speech_seen: bool,
+    silence_run_ms: u32,
+    frames: Vec<bool>,
+    energies: Vec<f32>,
+}
+
+impl VoiceActivityDetector {
+    pub fn new(sample_rate: u32, silence_ms: u32) -> Self {
+        let frame_len = (sample_rate * FRAME_MS / 1000) as usize;
+        let fft_len = frame_len.next_power_of_two();
+        let window = (0..frame_len)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / (frame_len - 1) as f32).cos())
+            .collect();
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(fft_len),
+            window,
+            frame_len,
+            sample_rate,
+            threshold_db: 10.0,
+            silence_ms,
+            pending: Vec::with_capacity(frame_len),
+            noise_db: None,
+            speech_run: 0,
+            speech_seen: false,
+            silence_run_ms: 0,
+            frames: Vec::new(),
+            energies: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, samples: &[i16]) {
+        for &sample in samples {
+            self.pending.push(sample as f32 / i16::MAX as f32);
+            if self.pending.len() == self.frame_len {
+                let frame = std::mem::replace(&mut self.pending, Vec::with_capacity(self.frame_len));
+                let speech = self.classify(&frame);
+                self.frames.push(speech);
+            }
+        }
+    }
+
+    // True once speech was heard and has been followed by the configured silence.
+    pub fn should_stop(&self) -> bool {
+        self.speech_seen && self.silence_run_ms >= self.silence_ms
+    }
+
+    pub fn speech_seen(&self) -> bool {
+        self.speech_seen
+    }
+
+    // Per-frame speech decisions so far, FRAME_MS each.
+    pub fn frames(&self) -> &[bool] {
+        &self.frames
+    }
+
+    fn classify(&mut self, frame: &[f32]) -> bool {
+        let energy = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+        let energy_db = 10.0 * (energy + 1e-10).log10();
+        self.energies.push(energy_db);
+
+        let mut spectrum: Vec<Complex<f32>> = frame
+            .iter()
+            .zip(&self.window)
+            .map(|(s, w)| Complex::new(s * w, 0.0))
+            .collect();
+        spectrum.resize(self.fft.len(), Complex::new(0.0, 0.0));
+        self.fft.process(&mut spectrum);
+        let bin_hz = self.sample_rate as f32 / spectrum.len() as f32;
+        let (mut band, mut total) = (0.0, 0.0);
+        for (bin, value) in spectrum.iter().take(spectrum.len() / 2).enumerate().skip(1) {
+            let power = value.norm_sqr();
+            let hz = bin as f32 * bin_hz;
+            if hz >= SPEECH_BAND_HZ.0 && hz <= SPEECH_BAND_HZ.1 {
+                band += power;
+            }
+            total += power;
+        }
+        let band_ratio = if total > 0.0 { band / total } else { 0.0 };
+
+        // The noise floor follows quiet frames quickly and loud ones slowly, so speech
+        // right at the start of a recording does not pin it high.
+        let noise_db = self.noise_db.unwrap_or(energy_db);
+        let rate = if energy_db < noise_db { 0.1 } else { 0.005 };
+        self.noise_db = Some(noise_db + rate * (energy_db - noise_db));
+
+        let speech = energy_db > MIN_SPEECH_DB && energy_db > noise_db + self.threshold_db && band_ratio > MIN_BAND_RATIO;
+        if speech {
+            self.speech_run += 1;
+            if self.speech_run >= SPEECH_ONSET_FRAMES {
+                self.speech_seen = true;
+            }
+            self.silence_run_ms = 0;
+        } else {
+            self.speech_run = 0;
+            self.silence_run_ms += FRAME_MS;
+        }
+        speech
+    }
+}
+
+// Listens for the user talking over the app's own speech. The microphone also hears the
+// speakers, so speech only counts when it is well above the echo expected from the current
+// playback level; the speaker-to-microphone coupling is learned while the user is quiet.
+pub struct BargeInDetector {
+    vad: VoiceActivityDetector,
+    seen: usize,
+    coupling_db: f32,
+    playback_db: f32,
+    speech_ms: u32,
+}
+
+impl BargeInDetector {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            vad: VoiceActivityDetector::new(sample_rate, u32::MAX),
+            seen: 0,
+            coupling_db: 0.0,
+            playback_db: -100.0,
+            speech_ms: 0,
+        }
+    }
+
+    // Returns true once the user has been talking over the playback long enough.
+    pub fn push(&mut self, samples: &[i16], playback_db: f32) -> bool {
+        self.vad.push(samples);
+        for index in self.seen..self.vad.frames.len() {
+            // Room echo lingers after the output drops, so the reference decays slowly.
+            self.playback_db = playback_db.max(self.playback_db - 1.0);
+            let mic_db = self.vad.energies[index];
+            let excess = mic_db - (self.playback_db + self.coupling_db);
+            if excess < BARGE_IN_MARGIN_DB / 2.0 {
+                self.coupling_db += 0.05 * (mic_db - self.playback_db - self.coupling_db);
+            }
+            if self.vad.frames[index] && excess > BARGE_IN_MARGIN_DB {
+                self.speech_ms += FRAME_MS;
+            } else {
+                self.speech_ms = 0;
+            }
+        }
+        self.seen = self.vad.frames.len();
+        self.speech_ms >= BARGE_IN_MS
+    }
+}
+
+// Sample range covering the detected speech plus some padding, or None when nothing was said.
+pub fn speech_range(samples: &[i16], sample_rate: u32) -> Option<std::ops::Range<usize>> {
+    let mut detector = VoiceActivityDetector::new(sample_rate, u32::MAX);
+    detector.push(samples);
+    if !detector.speech_seen() {
+        return None;
+    }
+    let frames = detector.frames();
+    let first = frames.iter().position(|&speech| speech)?;
+    let last = frames.iter().rposition(|&speech| speech)?;
+    let frame_len = (sample_rate * FRAME_MS / 1000) as usize;
+    let padding = (sample_rate * TRIM_PADDING_MS / 1000) as usize;
+    let start = (first * frame_len).saturating_sub(padding);
+    let end = ((last + 1) * frame_len + padding).min(samples.len());
+    Some(start..end)
+}
+
+pub fn trim_silence(samples: &[i16], sample_rate: u32) -> &[i16] {
+    match speech_range(samples, sample_rate) {
+        Some(range) => &samples[range],
+        None => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16_000;
+
+    // Quiet hiss around -65 dB, well under the speech floor; a fixed seed keeps runs repeatable.
+    fn hiss(ms: u32) -> Vec<i16> {
+        let mut seed: u32 = 12345;
+        (0..RATE * ms / 1000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((seed >> 16) as i16 % 33) - 16
+            })
+            .collect()
+    }
+
+    fn tone(ms: u32, hz: f32) -> Vec<i16> {
+        (0..RATE * ms / 1000)
+            .map(|i| (0.3 * (2.0 * std::f32::consts::PI * hz * i as f32 / RATE as f32).sin() * i16::MAX as f32) as i16)
+            .collect()
+    }
+
+    fn samples(ms: u32) -> usize {
+        (RATE * ms / 1000) as usize
+    }
+
+    // Range boundaries land within one frame of the expected sample.
+    fn assert_near(actual: usize, expected_ms: u32) {
+        let expected = samples(expected_ms);
+        let frame = samples(FRAME_MS);
+        assert!(actual.abs_diff(expected) <= frame, "got sample {}, expected about {}", actual, expected);
+    }
+
+    #[test]
+    fn silence_has_no_speech() {
+        let audio = hiss(2000);
+        let mut detector = VoiceActivityDetector::new(RATE, 300);
+        detector.push(&audio);
+        assert!(!detector.speech_seen());
+        assert!(!detector.should_stop());
+        assert!(detector.frames().iter().all(|&speech| !speech));
+        assert_eq!(speech_range(&audio, RATE), None);
+        assert!(trim_silence(&audio, RATE).is_empty());
+    }
+
+    #[test]
+    fn tone_burst_is_found_and_padded() {
+        let audio = [hiss(500), tone(1000, 440.0), hiss(500)].concat();
+        let range = speech_range(&audio, RATE).expect("speech in the burst");
+        assert_near(range.start, 500 - TRIM_PADDING_MS);
+        assert_near(range.end, 1500 + TRIM_PADDING_MS);
+        assert_eq!(trim_silence(&audio, RATE).len(), range.len());
+    }
+
+    #[test]
+    fn padding_is_clamped_to_the_recording() {
+        // The detector needs a few quiet frames to learn the noise floor.
+        let audio = [hiss(100), tone(500, 440.0), hiss(100)].concat();
+        let range = speech_range(&audio, RATE).expect("speech near the start");
+        assert_eq!(range.start, 0);
+        assert_eq!(range.end, audio.len());
+    }
+
+    #[test]
+    fn gap_after_speech_stops_and_more_speech_resumes() {
+        let mut detector = VoiceActivityDetector::new(RATE, 300);
+        detector.push(&hiss(300));
+        detector.push(&tone(500, 300.0));
+        assert!(detector.speech_seen());
+        assert!(!detector.should_stop());
+        detector.push(&hiss(200));
+        assert!(!detector.should_stop(), "200 ms of silence is shorter than the limit");
+        detector.push(&hiss(300));
+        assert!(detector.should_stop());
+        detector.push(&tone(500, 300.0));
+        assert!(!detector.should_stop());
+
+        // Speech, then silence, then speech again, in frame order.
+        let runs: Vec<bool> = detector.frames().iter().fold(Vec::new(), |mut runs, &speech| {
+            if runs.last() != Some(&speech) {
+                runs.push(speech);
+            }
+            runs
+        });
+        assert_eq!(runs, [false, true, false, true]);
+    }
+
+    #[test]
+    fn gap_stays_inside_the_trimmed_range() {
+        let audio = [hiss(400), tone(400, 440.0), hiss(600), tone(400, 440.0), hiss(400)].concat();
+        let range = speech_range(&audio, RATE).expect("speech on both sides of the gap");
+        assert_near(range.start, 400 - TRIM_PADDING_MS);
+        assert_near(range.end, 1800 + TRIM_PADDING_MS);
+    }
+
+    // Decodes a WAV the way uploaded recordings are decoded and runs it through the detector.
+    fn speech_range_in_wav(path: &std::path::Path) -> Option<std::ops::Range<usize>> {
+        let samples = crate::stt::decode_audio_file(path).expect("readable WAV");
+        speech_range(&samples, RATE)
+    }
+
+    // A device-style recording: 44.1 kHz stereo, the voice only on the left channel.
+    fn write_wav(path: &std::path::Path, mono: &[i16]) {
+        let spec = hound::WavSpec { channels: 2, sample_rate: 44_100, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        let mut resampler = crate::resample::Resampler::new(RATE, 44_100);
+        let mut upsampled = resampler.process(&mono.iter().map(|&s| s as f32 / i16::MAX as f32).collect::<Vec<_>>());
+        upsampled.extend(resampler.flush());
+        for sample in upsampled {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn wav_file_runs_through_the_same_detector() {
+        let path = std::env::temp_dir().join(format!("vad-test-{}.wav", std::process::id()));
+        write_wav(&path, &[hiss(500), tone(1000, 440.0), hiss(500)].concat());
+        let range = speech_range_in_wav(&path);
+        let _ = std::fs::remove_file(&path);
+        let range = range.expect("speech in the WAV");
+        assert_near(range.start, 500 - TRIM_PADDING_MS);
+        assert_near(range.end, 1500 + TRIM_PADDING_MS);
+    }
+
+    #[test]
+    fn out_of_band_tone_is_not_speech() {
+        let audio = [hiss(300), tone(1000, 7000.0)].concat();
+        assert_eq!(speech_range(&audio, RATE), None);
+    }
+}